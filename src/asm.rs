@@ -0,0 +1,235 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    genes::Genes,
+    network::{DecodedSynapse, Node},
+};
+
+// header byte when no rate is given, a mutation rate multiplier of 1
+const DEFAULT_RATE: u16 = 128;
+
+#[derive(Debug, PartialEq)]
+pub enum AsmErrorKind {
+    // not a line the assembler knows
+    Syntax(String),
+    // no synapse gene decodes to one starting or ending there
+    Source(Node),
+    Target(Node),
+    // not a multiple of 1/32 in -4..4
+    Weight(String),
+    // past the internal count the program asks for
+    Missing(Node, u16),
+    Internal(String),
+    Rate(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::Syntax(line) => write!(
+                f,
+                "expected internal <count>, rate <0-255> or <from> -> <to> w=<weight>, got {}",
+                line
+            ),
+            AsmErrorKind::Source(node) => write!(
+                f,
+                "no gene starts a synapse at {}, only inputs and internal:0 to internal:7 can",
+                node
+            ),
+            AsmErrorKind::Target(node) => write!(
+                f,
+                "no gene ends a synapse at {}, only outputs and internal:0 to internal:7 can",
+                node
+            ),
+            AsmErrorKind::Weight(w) => write!(
+                f,
+                "weight {} can't be stored, it has to be a multiple of 1/32 from -4 to 3.96875",
+                w
+            ),
+            AsmErrorKind::Missing(node, n) => write!(
+                f,
+                "{} doesn't exist with {} internal neurons, the synapse would be dropped",
+                node, n
+            ),
+            AsmErrorKind::Internal(n) => {
+                write!(f, "{} is not an internal neuron count, expected 0 to 15", n)
+            }
+            AsmErrorKind::Rate(r) => write!(f, "{} is not a rate, expected 0 to 255", r),
+        }
+    }
+}
+impl Error for AsmError {}
+
+// the gene for one synapse, checked by decoding it again
+fn encode(from: Node, to: Node, weight: &str) -> Result<u16, AsmErrorKind> {
+    let index = |node| match node {
+        Node::Input(i) | Node::Internal(i) | Node::Output(i) => i as u16,
+    };
+    let internal = |node| matches!(node, Node::Internal(_)) as u16;
+    let w: f32 = weight
+        .parse()
+        .map_err(|_| AsmErrorKind::Weight(weight.to_string()))?;
+    let steps = w * 32.;
+    if steps.fract() != 0. || !(i8::MIN as f32..=i8::MAX as f32).contains(&steps) {
+        return Err(AsmErrorKind::Weight(weight.to_string()));
+    }
+    let gene = internal(from) << 15
+        | (index(from) & 7) << 12
+        | internal(to) << 11
+        | (index(to) & 7) << 8
+        | (steps as i8 as u8 as u16);
+    let decoded = DecodedSynapse::new(gene);
+    if decoded.from != from {
+        return Err(AsmErrorKind::Source(from));
+    }
+    if decoded.to != to {
+        return Err(AsmErrorKind::Target(to));
+    }
+    Ok(gene)
+}
+
+// Builds a genome from a listing like disasm prints, one statement per line:
+//   internal 4                          internal neuron count, defaults to just enough
+//   rate 128                            header mutation rate byte, defaults to 128
+//   input:chem-x -> output:move-x w=-3  one synapse gene each, in order
+// and anything after a # is a comment. Errors instead of writing genes Network::new
+// would drop or decode to something else
+pub fn assemble(text: &str) -> Result<Genes, AsmError> {
+    let mut internal: Option<u16> = None;
+    let mut rate = DEFAULT_RATE;
+    // with the line each came from, to check against the internal count at the end
+    let mut synapses: Vec<(usize, u16, Node, Node)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let err = |kind| AsmError { line: i + 1, kind };
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["internal", n] => {
+                let n = n
+                    .parse()
+                    .ok()
+                    .filter(|n| *n < 16)
+                    .ok_or_else(|| err(AsmErrorKind::Internal(n.to_string())))?;
+                internal = Some(n);
+            }
+            ["rate", r] => {
+                rate = r
+                    .parse::<u8>()
+                    .map_err(|_| err(AsmErrorKind::Rate(r.to_string())))?
+                    as u16;
+            }
+            [from, "->", to, weight] if weight.starts_with("w=") => {
+                let node = |s: &str| {
+                    s.parse::<Node>()
+                        .map_err(|_| err(AsmErrorKind::Syntax(line.to_string())))
+                };
+                let (from, to) = (node(from)?, node(to)?);
+                let gene = encode(from, to, &weight[2..]).map_err(err)?;
+                synapses.push((i + 1, gene, from, to));
+            }
+            _ => return Err(err(AsmErrorKind::Syntax(line.to_string()))),
+        }
+    }
+
+    let needed = synapses
+        .iter()
+        .flat_map(|(_, _, from, to)| [*from, *to])
+        .filter_map(|node| match node {
+            Node::Internal(i) => Some(i as u16 + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let n_internal = internal.unwrap_or(needed);
+    for (line, _, from, to) in &synapses {
+        if let Some(node) = [*from, *to]
+            .into_iter()
+            .find(|node| !node.exists(n_internal as usize))
+        {
+            return Err(AsmError {
+                line: *line,
+                kind: AsmErrorKind::Missing(node, n_internal),
+            });
+        }
+    }
+    Ok(Genes {
+        header: n_internal << 12 | rate,
+        synapses: synapses.iter().map(|(_, gene, _, _)| *gene).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asm::{assemble, AsmErrorKind},
+        disasm::disassemble,
+        genes::Genes,
+        network::Node,
+    };
+
+    #[test]
+    fn round_trips_through_disasm() {
+        let genes = assemble(
+            "# steer by the chemicals\n\
+            input:chem-x -> output:move-x w=-3\n\
+            internal:2 -> output:move-x w=1.5  # a neuron nothing feeds sits at 0.5\n\
+            input:1 -> internal:5 w=0.03125\n",
+        )
+        .unwrap();
+        assert_eq!(genes.header, 0x6080);
+        assert_eq!(genes.to_string(), "blob2:6080.00a0.a030.1d01");
+        // every synapse is kept, and disasm prints something that assembles to the same genes
+        let listing = disassemble(&genes);
+        assert!(listing.synapses.iter().all(|line| line.kind.is_some()));
+        assert_eq!(assemble(&listing.to_string()), Ok(genes));
+
+        // an evolved genome keeps only what Network::new keeps
+        let old: Genes = "blob2:1080.13c3.00ee.beea.0d40".parse().unwrap();
+        let kept = assemble(&disassemble(&old).to_string()).unwrap();
+        assert_eq!(kept.synapses, vec![0x13c3, 0x00ee]);
+    }
+
+    #[test]
+    fn rejects_what_the_layout_cant_hold() {
+        let kind = |text: &str| assemble(text).unwrap_err().kind;
+        assert!(
+            assemble("input:energy -> internal:4 w=1.5\ninternal:4 -> output:move-x w=1").is_ok()
+        );
+        assert_eq!(
+            kind("internal:8 -> output:move-x w=1"),
+            AsmErrorKind::Source(Node::Internal(8))
+        );
+        assert_eq!(
+            kind("output:move-x -> output:move-y w=1"),
+            AsmErrorKind::Source(Node::Output(0))
+        );
+        assert_eq!(
+            kind("input:chem-x -> internal:9 w=1"),
+            AsmErrorKind::Target(Node::Internal(9))
+        );
+        assert_eq!(
+            kind("input:chem-x -> output:consume w=0.1"),
+            AsmErrorKind::Weight("0.1".to_string())
+        );
+        assert_eq!(
+            kind("input:chem-x -> output:consume w=4"),
+            AsmErrorKind::Weight("4".to_string())
+        );
+        assert_eq!(
+            kind("internal 2\ninternal:3 -> output:move-y w=1"),
+            AsmErrorKind::Missing(Node::Internal(3), 2)
+        );
+        assert!(matches!(kind("rate 300"), AsmErrorKind::Rate(_)));
+        assert!(matches!(kind("input:chem-x => output:move-x"), AsmErrorKind::Syntax(_)));
+        assert_eq!(assemble("\n\nnope").unwrap_err().line, 3);
+    }
+}
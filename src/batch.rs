@@ -0,0 +1,306 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use bevy::{core::DefaultTaskPoolOptions, prelude::App, MinimalPlugins};
+use serde::Serialize;
+
+use crate::{
+    blob::{Blob, CurBlobs},
+    food::CurFood,
+    genes::Genes,
+    hall::HallOfFame,
+    Config, SimulationPlugin, Tick, TickLimit,
+};
+
+// genomes kept per run in the summary
+const BEST_PER_RUN: usize = 3;
+
+// One headless world to run
+#[derive(Clone)]
+pub struct RunSpec {
+    // used for the run's output directory and in the summary
+    pub name: String,
+    pub config: Config,
+    pub seed: u64,
+    pub ticks: u64,
+    // note the tick the highest generation first reaches this
+    pub target_generation: Option<u16>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BestGenome {
+    pub genome: String,
+    pub generation: u16,
+    pub energy: f32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RunSummary {
+    pub name: String,
+    pub seed: u64,
+    pub ticks: u64,
+    pub final_blobs: u32,
+    pub final_food: u32,
+    // highest generation seen at any point in the run
+    pub max_generation: u16,
+    pub max_generation_genome: String,
+    // tick target_generation was reached, if it was
+    pub reached_target: Option<u64>,
+    // highest generation survivors at the end, most energy first on ties
+    pub best: Vec<BestGenome>,
+}
+
+// min, mean and max of one outcome over all runs
+#[derive(Serialize)]
+pub struct Spread {
+    pub min: f32,
+    pub mean: f32,
+    pub max: f32,
+}
+impl Spread {
+    pub(crate) fn of(values: impl Iterator<Item = f32>) -> Spread {
+        let values: Vec<f32> = values.collect();
+        if values.is_empty() {
+            return Spread {
+                min: 0.,
+                mean: 0.,
+                max: 0.,
+            };
+        }
+        Spread {
+            min: values.iter().copied().fold(f32::MAX, f32::min),
+            mean: values.iter().sum::<f32>() / values.len() as f32,
+            max: values.iter().copied().fold(f32::MIN, f32::max),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BatchSummary {
+    pub final_blobs: Spread,
+    pub max_generation: Spread,
+    pub runs: Vec<RunSummary>,
+}
+impl BatchSummary {
+    pub fn new(runs: Vec<RunSummary>) -> Self {
+        BatchSummary {
+            final_blobs: Spread::of(runs.iter().map(|r| r.final_blobs as f32)),
+            max_generation: Spread::of(runs.iter().map(|r| r.max_generation as f32)),
+            runs,
+        }
+    }
+
+    // summary.json with everything, summary.csv with one row per run
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        let json = BufWriter::new(File::create(dir.join("summary.json"))?);
+        serde_json::to_writer_pretty(json, self)?;
+
+        let mut csv = BufWriter::new(File::create(dir.join("summary.csv"))?);
+        writeln!(
+            csv,
+            "name,seed,ticks,final_blobs,final_food,max_generation,max_generation_genome,best_genome"
+        )?;
+        for run in &self.runs {
+            writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                run.name,
+                run.seed,
+                run.ticks,
+                run.final_blobs,
+                run.final_food,
+                run.max_generation,
+                run.max_generation_genome,
+                run.best.first().map(|b| b.genome.as_str()).unwrap_or(""),
+            )?;
+        }
+        csv.flush()
+    }
+}
+
+// Runs one world to completion on the calling thread.
+// Anything the world writes (metrics, snapshots) goes to out/<name>
+pub fn run_headless(spec: &RunSpec, out: &Path) -> RunSummary {
+    let dir = out.join(&spec.name);
+    let _ = fs::create_dir_all(&dir);
+
+    let mut app = App::new();
+    // worlds run side by side, so each one gets a single thread
+    // the limit only makes the last update count as the end of the run
+    app.insert_resource(DefaultTaskPoolOptions::with_num_threads(1))
+        .insert_resource(TickLimit(spec.ticks))
+        .add_plugins(MinimalPlugins)
+        .add_plugin(SimulationPlugin {
+            config: spec.config.clone(),
+            seed: spec.seed,
+            out: dir,
+        });
+    let mut reached_target = None;
+    for _ in 0..spec.ticks {
+        app.update();
+        if let (Some(target), None) = (spec.target_generation, reached_target) {
+            if app.world.resource::<HallOfFame>().max_generation() >= target {
+                reached_target = Some(app.world.resource::<Tick>().0);
+            }
+        }
+    }
+
+    let world = &mut app.world;
+    let hall = world.resource::<HallOfFame>();
+    let max_generation = hall.max_generation();
+    let max_generation_genome = hall
+        .generation
+        .first()
+        .map(|e| e.genome.clone())
+        .unwrap_or_default();
+    let final_blobs = world.resource::<CurBlobs>().0;
+    let final_food = world.resource::<CurFood>().0;
+    let mut best: Vec<BestGenome> = world
+        .query::<(&Blob, &Genes)>()
+        .iter(world)
+        .map(|(blob, genes)| BestGenome {
+            genome: genes.to_string(),
+            generation: blob.generation,
+            energy: blob.energy,
+        })
+        .collect();
+    best.sort_by(|a, b| {
+        b.generation
+            .cmp(&a.generation)
+            .then(b.energy.total_cmp(&a.energy))
+    });
+    best.truncate(BEST_PER_RUN);
+
+    RunSummary {
+        name: spec.name.clone(),
+        seed: spec.seed,
+        ticks: spec.ticks,
+        final_blobs,
+        final_food,
+        max_generation,
+        max_generation_genome,
+        reached_target,
+        best,
+    }
+}
+
+// Runs every spec on `jobs` threads, results come back in the order of `specs`.
+// `done` is called as each run finishes, with the number finished so far
+pub fn run_batch(
+    specs: &[RunSpec],
+    jobs: usize,
+    out: &Path,
+    done: impl Fn(usize, &RunSummary) + Sync,
+) -> Vec<RunSummary> {
+    let next = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<RunSummary>>> = Mutex::new(vec![None; specs.len()]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(specs.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let spec = match specs.get(i) {
+                    Some(spec) => spec,
+                    None => break,
+                };
+                let summary = run_headless(spec, out);
+                done(finished.fetch_add(1, Ordering::SeqCst) + 1, &summary);
+                results.lock().unwrap()[i] = Some(summary);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.expect("every run finishes"))
+        .collect()
+}
+
+// name for a run of `config_name` with `seed`
+pub fn run_name(config_name: &str, seed: u64) -> String {
+    format!("{}-{}", config_name, seed)
+}
+
+// default number of worker threads
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+// file stem of a config path, "default" for the built in one
+pub fn config_name(path: Option<&PathBuf>) -> String {
+    path.and_then(|p| p.file_stem())
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "default".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Mutex};
+
+    use crate::{
+        batch::{run_batch, run_headless, run_name, BatchSummary, RunSpec},
+        Config,
+    };
+
+    #[test]
+    fn runs_come_back_in_spec_order() {
+        let out = std::env::temp_dir().join(format!("blobs-batch-{}", std::process::id()));
+        // a different population per seed, so runs can't be told apart by chance
+        let specs: Vec<RunSpec> = [7, 8, 9]
+            .into_iter()
+            .map(|seed| {
+                let mut config = Config::default();
+                config.blobs.min_blobs = 4 * (seed as u32 - 5);
+                RunSpec {
+                    name: run_name("small", seed),
+                    config,
+                    seed,
+                    ticks: 90,
+                    target_generation: None,
+                }
+            })
+            .collect();
+
+        let finished = Mutex::new(Vec::new());
+        let runs = run_batch(&specs, 2, &out, |n, _| finished.lock().unwrap().push(n));
+        let mut finished = finished.into_inner().unwrap();
+        finished.sort();
+        assert_eq!(finished, vec![1, 2, 3]);
+
+        let names: Vec<&str> = runs.iter().map(|run| run.name.as_str()).collect();
+        assert_eq!(names, vec!["small-7", "small-8", "small-9"]);
+        // a run gives the same result on a worker thread as on its own
+        let alone = run_headless(&specs[1], &out);
+        assert_eq!(runs[1].final_blobs, alone.final_blobs);
+        assert_eq!(runs[1].best[0].genome, alone.best[0].genome);
+
+        let blobs: Vec<u32> = runs.iter().map(|run| run.final_blobs).collect();
+        assert_eq!(blobs, vec![8, 12, 16]);
+        let summary = BatchSummary::new(runs);
+        assert_eq!(
+            (summary.final_blobs.min, summary.final_blobs.mean, summary.final_blobs.max),
+            (8., 12., 16.)
+        );
+        summary.write(&out).unwrap();
+        let csv = fs::read_to_string(out.join("summary.csv")).unwrap();
+        let _ = fs::remove_dir_all(&out);
+        let seeds: Vec<&str> = csv
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').nth(1).unwrap())
+            .collect();
+        assert_eq!(seeds, vec!["7", "8", "9"]);
+    }
+}
@@ -1,75 +1,168 @@
 use bevy::{
-    core::FixedTimestep,
-    math::{Vec2, Vec3},
+    ecs::system::SystemParam,
+    log::{error, info},
+    math::Vec3,
     prelude::{
-        App, Color, Commands, Component, Entity, Plugin, Query, Res, ResMut, SystemSet, Transform,
-        With,
+        App, Commands, Component, Entity, EventWriter, ParallelSystemDescriptorCoercion, Plugin,
+        Query, Res, ResMut, SystemLabel, SystemSet, Transform, TransformBundle, With,
     },
-    sprite::{Sprite, SpriteBundle},
     tasks::ComputeTaskPool,
+    utils::HashSet,
 };
 use rand::Rng;
 
 use crate::{
+    brain::{Actions, Brain, BrainBuilder, Senses},
+    config::{BlobConfig, GeneConfig},
     food::{EatenFood, Food},
     genes::Genes,
-    network::Network,
-    Chem, Stages, WinSize,
+    once_per_second,
+    rng::{SimRng, Stream},
+    Chem, SimLabel, Stages, Tick, WinSize,
 };
 use crate::{Acceleration, Velocity};
 
 #[derive(Component)]
-struct Blob {
-    energy: f32,
-    age: f32,
-    generation: u16,
-    brain: Network,
+pub struct Blob {
+    pub energy: f32,
+    pub age: f32,
+    pub generation: u16,
+    pub brain: Box<dyn Brain>,
+    // what the brain wanted last tick
+    pub actions: Actions,
+    // gave its last energy trying to replicate and hasn't eaten since
+    pub exhausted: bool,
+    pub offspring: u32,
+    pub food_eaten: u32,
+    // most energy it has had at once
+    pub peak_energy: f32,
+    // unique within a run, parents is empty for blobs that were spawned rather than born
+    pub id: u64,
+    pub parents: Vec<u64>,
+    // tick it came into the world, and the energy it came with
+    pub born: u64,
+    pub birth_energy: f32,
 }
 
-struct OldestBlob((u128, u16));
-impl Default for OldestBlob {
-    fn default() -> Self {
-        Self((0u128, 0u16))
-    }
+// A blob came into the world, either topped up at random or as someone's child
+pub struct BlobBorn {
+    pub generation: u16,
+    pub random: bool,
 }
 
-struct CurBlobs(u32);
-impl Default for CurBlobs {
-    fn default() -> Self {
-        Self(0)
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeathCause {
+    // ran out of energy moving and living
+    Starved,
+    // ran out after spending everything on a failed replication
+    Exhausted,
 }
 
-struct MinBlobs(u32);
-impl Default for MinBlobs {
-    fn default() -> Self {
-        Self(32)
-    }
+pub struct BlobDied {
+    pub cause: DeathCause,
+    pub id: u64,
+    pub generation: u16,
+    pub age: f32,
+    pub offspring: u32,
+    pub food_eaten: u32,
+    pub peak_energy: f32,
 }
 
-#[derive(Component)]
-struct EatenChems(Vec<(Entity, Vec3)>);
-impl Default for EatenChems {
-    fn default() -> Self {
-        Self(Vec::new())
+#[derive(Default)]
+pub struct CurBlobs(pub u32);
+
+// id the next blob gets
+#[derive(Default)]
+pub struct NextBlobId(pub u64);
+
+// Everything bringing a blob into the world touches
+#[derive(SystemParam)]
+struct Births<'w, 's> {
+    cur_blobs: ResMut<'w, CurBlobs>,
+    next_id: ResMut<'w, NextBlobId>,
+    tick: Res<'w, Tick>,
+    brains: Res<'w, BrainBuilder>,
+    events: EventWriter<'w, 's, BlobBorn>,
+}
+impl<'w, 's> Births<'w, 's> {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        trans: Vec3,
+        gene: Genes,
+        energy: f32,
+        generation: u16,
+        parents: Vec<u64>,
+    ) {
+        self.events.send(BlobBorn {
+            generation,
+            random: parents.is_empty(),
+        });
+        commands
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(trans),
+            ))
+            .insert(Blob {
+                energy,
+                brain: self.brains.build(&gene),
+                actions: Actions::default(),
+                age: 0.,
+                generation,
+                exhausted: false,
+                offspring: 0,
+                food_eaten: 0,
+                peak_energy: energy,
+                id: self.next_id.0,
+                parents,
+                born: self.tick.0,
+                birth_energy: energy,
+            })
+            .insert(Velocity::default())
+            .insert(Acceleration::default())
+            .insert(gene);
+        self.next_id.0 += 1;
+        self.cur_blobs.0 += 1;
     }
 }
 
+// Genomes to put in the world at startup, next to whatever a snapshot brings
+pub struct SeedGenomes(pub Vec<Genes>);
+
+// births, deaths and top ups all touch CurBlobs and spawn or despawn blobs,
+// so they always run in this order
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+enum BlobLabel {
+    Replicate,
+    Kill,
+}
+
 pub struct BlobPlugin;
 impl Plugin for BlobPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(CurBlobs::default())
-            .insert_resource(MinBlobs::default())
-            .insert_resource(OldestBlob::default())
+            .insert_resource(NextBlobId::default())
+            .init_resource::<BrainBuilder>()
+            .add_event::<BlobBorn>()
+            .add_event::<BlobDied>()
+            .add_startup_system(spawn_seeds.after(SimLabel::Restore))
             .add_system_set(
                 SystemSet::new()
-                    .with_run_criteria(FixedTimestep::step(1.0))
-                    .with_system(spawn_blobs)
-                    .with_system(blob_replicate)
-                    .with_system(get_oldest),
+                    .with_run_criteria(once_per_second)
+                    .with_system(spawn_blobs.after(BlobLabel::Kill))
+                    .with_system(
+                        blob_replicate
+                            .label(BlobLabel::Replicate)
+                            .after(SimLabel::Chems),
+                    )
+                    .with_system(blob_mate.label(BlobLabel::Replicate).after(SimLabel::Chems)),
             )
             .add_system_to_stage(Stages::BlobStage, blob_action)
-            .add_system(kill_blobs);
+            .add_system(
+                kill_blobs
+                    .label(BlobLabel::Kill)
+                    .after(BlobLabel::Replicate)
+                    .after(SimLabel::Chems),
+            );
     }
 }
 
@@ -79,69 +172,76 @@ impl Plugin for BlobPlugin {
 // Runs once per second, spawns blobs if there is less than needed
 fn spawn_blobs(
     mut commands: Commands,
-    mut cur_blobs: ResMut<CurBlobs>,
-    min_blobs: Res<MinBlobs>,
+    mut births: Births,
+    config: Res<BlobConfig>,
     win: Res<WinSize>,
+    rng: Res<SimRng>,
+    tick: Res<Tick>,
 ) {
-    let mut r = rand::thread_rng();
-    while cur_blobs.0 < min_blobs.0 {
-        spawn_blob(
+    let mut r = rng.stream(Stream::SpawnBlobs, tick.0);
+    while births.cur_blobs.0 < config.min_blobs {
+        births.spawn(
             &mut commands,
             Vec3::new(r.gen_range(0.0..win.w), r.gen_range(0.0..win.h), 0.9),
-            Genes::default(),
-            100.,
-            &mut cur_blobs,
+            Genes::random(&mut r),
+            config.start_energy,
             0,
+            vec![],
         );
     }
 }
 
-fn spawn_blob(
-    commands: &mut Commands,
-    trans: Vec3,
-    gene: Genes,
-    energy: f32,
-    cur_blobs: &mut ResMut<CurBlobs>,
-    generation: u16,
+// Success collection: genomes.txt, run it with --genomes genomes.txt
+fn spawn_seeds(
+    mut commands: Commands,
+    seeds: Option<Res<SeedGenomes>>,
+    mut births: Births,
+    config: Res<BlobConfig>,
+    win: Res<WinSize>,
+    rng: Res<SimRng>,
+    tick: Res<Tick>,
 ) {
-    let gen = gene.gene;
-    let r = ((gen & (255u128 << 120)) >> 120) as u8;
-    let g = ((gen & (255u128 << 112)) >> 112) as u8;
-    let b = ((gen & (255u128 << 106)) >> 106) as u8;
-    commands
-        .spawn_bundle(SpriteBundle {
-            sprite: Sprite {
-                color: Color::rgb_u8(r, g, b),
-                custom_size: Some(Vec2::new(5., 5.)),
-                ..Default::default()
-            },
-            transform: Transform {
-                translation: trans.clone(),
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Blob {
-            energy,
-            brain: Network::new(gene.clone()),
-            age: 0.,
-            generation,
-        })
-        .insert(Velocity::default())
-        .insert(Acceleration::default())
-        .insert(gene);
-    cur_blobs.0 += 1;
+    let seeds = match seeds {
+        Some(seeds) => seeds,
+        None => return,
+    };
+    let mut r = rng.stream(Stream::SeedBlobs, tick.0);
+    for genes in &seeds.0 {
+        births.spawn(
+            &mut commands,
+            Vec3::new(r.gen_range(0.0..win.w), r.gen_range(0.0..win.h), 0.9),
+            genes.clone(),
+            config.start_energy,
+            0,
+            vec![],
+        );
+    }
+    info!("Seeded {} blobs", seeds.0.len());
 }
 
 fn kill_blobs(
     mut commands: Commands,
     mut query: Query<(Entity, &Blob)>,
     mut cur_blobs: ResMut<CurBlobs>,
+    mut deaths: EventWriter<BlobDied>,
 ) {
     query.iter_mut().for_each(|(ent, blob)| {
         if blob.energy < 0. {
             commands.entity(ent).despawn();
             cur_blobs.0 -= 1;
+            deaths.send(BlobDied {
+                cause: if blob.exhausted {
+                    DeathCause::Exhausted
+                } else {
+                    DeathCause::Starved
+                },
+                id: blob.id,
+                generation: blob.generation,
+                age: blob.age,
+                offspring: blob.offspring,
+                food_eaten: blob.food_eaten,
+                peak_energy: blob.peak_energy,
+            });
         }
     });
 }
@@ -149,33 +249,144 @@ fn kill_blobs(
 fn blob_replicate(
     mut commands: Commands,
     mut query: Query<(&Transform, &Genes, &mut Blob)>,
-    mut cur_blobs: ResMut<CurBlobs>,
+    mut births: Births,
+    config: Res<BlobConfig>,
+    gene_config: Res<GeneConfig>,
+    rng: Res<SimRng>,
+    tick: Res<Tick>,
 ) {
-    let mut r = rand::thread_rng();
+    if gene_config.crossover.is_some() {
+        return;
+    }
+    let mut r = rng.stream(Stream::Replicate, tick.0);
+    let threshold = config.reproduce_threshold;
     query.for_each_mut(|(trans, gene, mut blob)| {
         // reproduce, but not too often
         // TODO: this should be done inside net? or just outside?
-        if blob.brain.outputs[3].weight > 0.3
-            && r.gen_bool((blob.brain.outputs[3].weight - 0.3) as f64)
+        if blob.actions[3] > threshold && r.gen_bool((blob.actions[3] - threshold) as f64)
         {
             // STOP SPAWNING SO MUCH AAAAAAA
-            if blob.energy <= 10. {
-                blob.energy = 0.
+            if blob.energy <= config.min_reproduce_energy {
+                blob.energy = 0.;
+                blob.exhausted = true;
             } else {
-                spawn_blob(
+                let child = match gene.replicate(&mut r, &gene_config) {
+                    Ok(child) => child,
+                    Err(e) => {
+                        error!("Could not replicate: {}", e);
+                        return;
+                    }
+                };
+                births.spawn(
                     &mut commands,
                     trans.translation,
-                    gene.replicate(),
+                    child,
                     blob.energy / 2.,
-                    &mut cur_blobs,
                     blob.generation + 1,
+                    vec![blob.id],
                 );
                 blob.energy /= 2.;
+                blob.offspring += 1;
             }
         }
     });
 }
 
+// Replication when a crossover is configured: every blob signalling reproduce is willing,
+// and the ones that would have cloned themselves look for the closest willing partner instead.
+// Each parent pays half of what cloning costs, a quarter of its energy
+fn blob_mate(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Transform, &Genes, &mut Blob)>,
+    mut births: Births,
+    config: Res<BlobConfig>,
+    gene_config: Res<GeneConfig>,
+    rng: Res<SimRng>,
+    tick: Res<Tick>,
+) {
+    let how = match gene_config.crossover {
+        Some(how) => how,
+        None => return,
+    };
+    let mut r = rng.stream(Stream::Replicate, tick.0);
+    let threshold = config.reproduce_threshold;
+    let willing: Vec<(Entity, Vec3, bool)> = query
+        .iter()
+        .filter(|(_, _, _, blob)| blob.actions[3] > threshold)
+        .map(|(ent, trans, _, blob)| {
+            let looking = r.gen_bool((blob.actions[3] - threshold) as f64);
+            (ent, trans.translation, looking)
+        })
+        .collect();
+
+    let mut taken = HashSet::default();
+    let mut pairs = Vec::new();
+    for (ent, loc, looking) in &willing {
+        if !looking || taken.contains(ent) {
+            continue;
+        }
+        let partner = willing
+            .iter()
+            .filter(|(other, other_loc, _)| {
+                other != ent
+                    && !taken.contains(other)
+                    && loc.distance_squared(*other_loc) < config.mate_radius_sq
+            })
+            .min_by(|a, b| {
+                loc.distance_squared(a.1)
+                    .total_cmp(&loc.distance_squared(b.1))
+            });
+        if let Some((partner, _, _)) = partner {
+            taken.insert(*ent);
+            taken.insert(*partner);
+            pairs.push((*ent, *partner));
+        }
+    }
+
+    for (a, b) in pairs {
+        // same rule as cloning, a parent that can't afford it loses everything
+        let mut can_afford = |ent: Entity| {
+            let (_, _, _, mut blob) = query.get_mut(ent).unwrap();
+            if blob.energy <= config.min_reproduce_energy {
+                blob.energy = 0.;
+                blob.exhausted = true;
+                false
+            } else {
+                true
+            }
+        };
+        // not &&, both parents pay for a failed attempt
+        if !(can_afford(a) & can_afford(b)) {
+            continue;
+        }
+
+        let (_, trans, genes_a, blob_a) = query.get(a).unwrap();
+        let (_, _, genes_b, blob_b) = query.get(b).unwrap();
+        let child = match genes_a
+            .crossover(genes_b, how, &mut r)
+            .replicate(&mut r, &gene_config)
+        {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Could not replicate: {}", e);
+                continue;
+            }
+        };
+        let generation = blob_a.generation.max(blob_b.generation) + 1;
+        let parents = vec![blob_a.id, blob_b.id];
+        let at = trans.translation;
+        let mut energy = 0.;
+        for ent in [a, b] {
+            let (_, _, _, mut blob) = query.get_mut(ent).unwrap();
+            energy += blob.energy / 4.;
+            blob.offspring += 1;
+            blob.energy -= blob.energy / 4.;
+        }
+
+        births.spawn(&mut commands, at, child, energy, generation, parents);
+    }
+}
+
 // Input nodes: sensor(s), oscillator, energy
 // n mid nodes
 // Output nodes: x_mov, y_mov, consume, reproduce
@@ -187,63 +398,52 @@ fn blob_action(
     chem_query: Query<(&Transform, With<Chem>)>,
     food_query: Query<(Entity, &Transform, &Food)>,
     mut eaten_food: ResMut<EatenFood>,
+    config: Res<BlobConfig>,
     pool: Res<ComputeTaskPool>,
 ) {
     let c: Vec<(&Transform, _)> = chem_query.iter().collect();
     blob_query.par_for_each_mut(&pool, 16, |(mut accel, blob_trans, mut blob)| {
         // update sensors, x and y dir for nearby chems
+        let mut senses = Senses::default();
         let blob_loc = blob_trans.translation;
         c.iter().for_each(|(trans, _)| {
             let loc = trans.translation;
             let dist = blob_loc.distance_squared(loc);
 
-            if dist < 100. {
-                blob.brain.inputs[0].cur_sum += blob_loc.x - loc.x;
-                blob.brain.inputs[1].cur_sum += blob_loc.y - loc.y;
+            if dist < config.sense_radius_sq {
+                senses[0] += blob_loc.x - loc.x;
+                senses[1] += blob_loc.y - loc.y;
             }
         });
-        blob.brain.inputs[0].activate();
-        blob.brain.inputs[1].activate();
-        blob.brain.inputs[0].cur_sum = 0.;
-        blob.brain.inputs[1].cur_sum = 0.;
         // energy level is second input
-        blob.brain.inputs[2].cur_sum = blob.energy;
-        blob.brain.inputs[2].activate();
-        blob.brain.inputs[2].cur_sum = 0.;
-
+        senses[2] = blob.energy;
         // oscillator
-        // activate or no?
-        blob.brain.inputs[3].weight = 0.5 + (blob.age * 10.).sin() / 2.;
-        // blob.brain.inputs[3].cur_sum = 0.5 + (blob.age * 10.).sin() / 2.;
-        // blob.brain.inputs[3].activate();
-        // blob.brain.inputs[3].cur_sum = 0.;
-
-        // this is bad
-        // get all things within
+        senses[3] = 0.5 + (blob.age * 10.).sin() / 2.;
 
-        // TODO: move results into blob and pull from there
-        let actions = blob.brain.eval();
+        let actions = blob.brain.think(&senses);
+        blob.actions = actions;
 
-        accel.0.x += actions.0;
-        accel.0.y += actions.1;
+        accel.0.x += actions[0];
+        accel.0.y += actions[1];
 
         // movement costs energy, scaling quadradically
-        let mov = actions.0.abs() + actions.1.abs();
-        blob.energy -= mov * mov / 5.;
+        let mov = actions[0].abs() + actions[1].abs();
+        blob.energy -= mov * mov * config.move_cost;
+        blob.energy -= blob.brain.energy_cost();
 
-        // if actions.2 {
+        // if actions[2] > 0.7 {
         //     // consume
         //     // TODO
         //     blob.energy -= 0.003;
         // }
 
-        // if actions.3 {
+        // if actions[3] > 0.7 {
         //     // try reproduce
         //     // TODO
         // }
 
         // die slowly....
-        blob.energy -= 0.001;
+        blob.energy -= config.metabolic_cost;
         blob.age += 0.001;
     });
 
@@ -251,24 +451,93 @@ fn blob_action(
         let blob_loc = trans.translation;
         food_query.for_each(|(ent, trans, food)| {
             let dist = blob_loc.distance_squared(trans.translation);
-            if dist < 10. {
+            if dist < config.eat_radius_sq {
                 eaten_food.0.insert(ent);
                 blob.energy += food.nutriton;
+                blob.food_eaten += 1;
+                blob.peak_energy = blob.peak_energy.max(blob.energy);
+                blob.exhausted = false;
             }
         });
     });
 }
 
-// Success collection:
-// - 27412239664388069923010120978984735311
-fn get_oldest(mut oldest: ResMut<OldestBlob>, query: Query<(&Blob, &Genes)>) {
-    query.for_each(|(blob, genes)| {
-        if blob.generation > oldest.0 .1 {
-            println!(
-                "New hightest gen blob! Gen: {} Geneome: {}",
-                blob.generation, genes.gene
-            );
-            oldest.0 = (genes.gene, blob.generation);
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        math::Vec3,
+        prelude::{App, Transform},
+    };
+
+    use crate::{
+        blob::{blob_mate, Blob, BlobBorn, CurBlobs, NextBlobId},
+        brain::BrainBuilder,
+        config::{BlobConfig, GeneConfig},
+        genes::{test_genes, Crossover},
+        network::Network,
+        rng::SimRng,
+        Tick,
+    };
+
+    fn willing(energy: f32) -> Blob {
+        Blob {
+            energy,
+            age: 0.,
+            generation: 0,
+            brain: Box::new(Network::new(&test_genes())),
+            actions: [0., 0., 0., 1.],
+            exhausted: false,
+            offspring: 0,
+            food_eaten: 0,
+            peak_energy: energy,
+            id: 0,
+            parents: vec![],
+            born: 0,
+            birth_energy: energy,
         }
-    });
+    }
+
+    #[test]
+    fn failed_pair_doesnt_stop_the_rest() {
+        let mut app = App::new();
+        app.insert_resource(BlobConfig {
+            // everyone signalling reproduce goes looking
+            reproduce_threshold: 0.,
+            ..Default::default()
+        })
+        .insert_resource(GeneConfig {
+            weight_sigma: -1.,
+            crossover: Some(Crossover::SinglePoint),
+            ..Default::default()
+        })
+        .insert_resource(SimRng::new(0))
+        .insert_resource(Tick(60))
+        .insert_resource(CurBlobs::default())
+        .insert_resource(NextBlobId::default())
+        .insert_resource(BrainBuilder::default())
+        .add_event::<BlobBorn>()
+        .add_system(blob_mate);
+        // a pair that can pay but whose child can't be made, then one that can't pay,
+        // far enough apart not to mix
+        for (x, energy) in [(0., 100.), (1., 100.), (500., 1.), (501., 1.)] {
+            app.world
+                .spawn()
+                .insert(Transform::from_translation(Vec3::new(x, 0., 0.)))
+                .insert(test_genes())
+                .insert(willing(energy));
+        }
+        app.update();
+
+        let blobs: Vec<(f32, bool)> = app
+            .world
+            .query::<&Blob>()
+            .iter(&app.world)
+            .map(|blob| (blob.energy, blob.exhausted))
+            .collect();
+        assert_eq!(
+            blobs,
+            vec![(100., false), (100., false), (0., true), (0., true)]
+        );
+        assert_eq!(app.world.resource::<CurBlobs>().0, 0);
+    }
 }
@@ -0,0 +1,86 @@
+use crate::{
+    config::BrainConfig,
+    genes::Genes,
+    network::{Network, INPUT_NAMES, OUTPUT_NAMES},
+};
+
+pub const N_SENSES: usize = INPUT_NAMES.len();
+pub const N_ACTIONS: usize = OUTPUT_NAMES.len();
+
+// What a blob feels every tick, in the order of INPUT_NAMES: how far it is from the
+// chemicals it can smell on x and y (summed over every one in range), its energy and
+// an oscillator going between 0 and 1
+pub type Senses = [f32; N_SENSES];
+
+// What it wants to do, in the order of OUTPUT_NAMES: move on x and y (-1 to 1),
+// then how much it wants to eat and to reproduce (0 to 1)
+pub type Actions = [f32; N_ACTIONS];
+
+// Anything that can steer a blob. The blob systems only ever go through this, so
+// hand written controllers or agents driven from outside can stand in for the
+// genome's network, see BrainBuilder
+pub trait Brain: Send + Sync {
+    fn think(&mut self, senses: &Senses) -> Actions;
+
+    // energy it uses every tick on top of the metabolic cost
+    fn energy_cost(&self) -> f32 {
+        0.
+    }
+
+    // the network to put in snapshots, brains without one are built again
+    // from the genome when a snapshot is restored
+    fn network(&self) -> Option<&Network> {
+        None
+    }
+}
+
+type MakeBrain = dyn Fn(&Genes) -> Box<dyn Brain> + Send + Sync;
+
+// Makes the brain of every new blob from its genome. Insert one before adding the
+// SimulationPlugin to use something other than the decoded Network
+pub struct BrainBuilder(pub Box<MakeBrain>);
+impl Default for BrainBuilder {
+    fn default() -> Self {
+        Self(Box::new(|genes| Box::new(Network::new(genes))))
+    }
+}
+impl BrainBuilder {
+    // the genome's network, recurrent if the config asks for it
+    pub fn network(config: &BrainConfig) -> BrainBuilder {
+        if !config.recurrent {
+            return BrainBuilder::default();
+        }
+        let leak = config.leak;
+        Self(Box::new(move |genes| {
+            Box::new(Network::new(genes).with_memory(leak))
+        }))
+    }
+
+    pub fn build(&self, genes: &Genes) -> Box<dyn Brain> {
+        (self.0)(genes)
+    }
+}
+
+impl Brain for Network {
+    fn think(&mut self, senses: &Senses) -> Actions {
+        // the chem and energy inputs are squashed, the oscillator goes in as it is
+        for (input, sense) in self.inputs.iter_mut().zip(&senses[..3]) {
+            input.cur_sum = *sense;
+            input.activate();
+            input.cur_sum = 0.;
+        }
+        self.inputs[3].weight = senses[3];
+
+        let (move_x, move_y, _, _) = self.eval();
+        [
+            move_x,
+            move_y,
+            self.outputs[2].weight,
+            self.outputs[3].weight,
+        ]
+    }
+
+    fn network(&self) -> Option<&Network> {
+        Some(self)
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+
+use bevy::log::Level;
+use blobs::{metrics::MetricsFormat, sprites::ColorMode};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+
+/// A relatively simple bacteria-inspired sim
+#[derive(Parser)]
+#[clap(name = "blobs", version)]
+pub struct Args {
+    /// Seed for every random draw in the run, picked at random if left out
+    #[clap(long, value_parser)]
+    pub seed: Option<u64>,
+
+    /// RON file with simulation parameters, see blobs.ron
+    #[clap(long, short, value_parser)]
+    pub config: Option<PathBuf>,
+
+    /// Run without a window or renderer
+    #[clap(long, action)]
+    pub headless: bool,
+
+    /// What blob colours show: genome, species, energy, age or generation (C cycles them)
+    #[clap(long, value_parser)]
+    pub color: Option<ColorMode>,
+
+    /// Stop after this many ticks
+    #[clap(long, value_parser)]
+    pub ticks: Option<u64>,
+
+    /// Snapshot to resume from, its seed replaces --seed
+    #[clap(long, value_parser)]
+    pub load: Option<PathBuf>,
+
+    /// File of genomes to start the world with, one per line
+    #[clap(long, value_parser)]
+    pub genomes: Option<PathBuf>,
+
+    /// Hall of fame from an earlier run to start the world with
+    #[clap(long, value_parser)]
+    pub from_hall: Option<PathBuf>,
+
+    /// Write a snapshot every this many ticks
+    #[clap(long, value_parser)]
+    pub snapshot_every: Option<u64>,
+
+    /// Directory snapshots and other output are written to
+    #[clap(long, short, default_value = ".", value_parser)]
+    pub out: PathBuf,
+
+    /// Write population metrics to <out>/metrics.csv or .jsonl (csv or json)
+    #[clap(long, value_parser)]
+    pub metrics: Option<MetricsFormat>,
+
+    /// Write every birth and death to <out>/lineage.csv
+    #[clap(long, action)]
+    pub lineage: bool,
+
+    /// Write the species found every species.interval ticks to <out>/species.csv
+    #[clap(long, action)]
+    pub species: bool,
+
+    /// Log verbosity: error, warn, info, debug or trace
+    #[clap(long, default_value = "info", value_parser)]
+    pub log: Level,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run many headless worlds in parallel and summarise how they ended
+    Batch(BatchArgs),
+    /// Run every combination of the parameter values in a sweep file
+    Sweep(SweepArgs),
+    /// Turn a lineage.csv from --lineage into a family tree (Newick and JSON)
+    Phylogeny(PhylogenyArgs),
+    /// Print what the network decoded from a genome looks like
+    Disasm(DisasmArgs),
+    /// Draw the networks of genomes as Graphviz DOT graphs
+    Dot(DotArgs),
+    /// Build genomes from hand written networks, the format disasm prints
+    Asm(AsmArgs),
+}
+
+#[derive(ClapArgs)]
+pub struct BatchArgs {
+    /// Number of seeds to run every config with
+    #[clap(long, default_value = "8", value_parser)]
+    pub runs: u64,
+
+    /// First seed, run i uses this plus i, picked at random if left out
+    #[clap(long, value_parser)]
+    pub seed: Option<u64>,
+
+    /// RON config to run, repeat to compare several, defaults to the built in one
+    #[clap(long, short, value_parser)]
+    pub config: Vec<PathBuf>,
+
+    /// Ticks every run lasts
+    #[clap(long, value_parser)]
+    pub ticks: u64,
+
+    /// Worlds run at once, defaults to the number of cores
+    #[clap(long, short, value_parser)]
+    pub jobs: Option<usize>,
+
+    /// Directory for summary.csv, summary.json and one subdirectory per run
+    #[clap(long, short, default_value = ".", value_parser)]
+    pub out: PathBuf,
+
+    /// Also write population metrics for every run (csv or json)
+    #[clap(long, value_parser)]
+    pub metrics: Option<MetricsFormat>,
+}
+
+#[derive(ClapArgs)]
+pub struct SweepArgs {
+    /// RON file with the ticks, replicates and parameter values to run
+    #[clap(value_parser)]
+    pub sweep: PathBuf,
+
+    /// RON config the sweep starts from, defaults to the built in one
+    #[clap(long, short, value_parser)]
+    pub config: Option<PathBuf>,
+
+    /// First seed, replicate i uses this plus i at every point
+    #[clap(long, value_parser)]
+    pub seed: Option<u64>,
+
+    /// Worlds run at once, defaults to the number of cores
+    #[clap(long, short, value_parser)]
+    pub jobs: Option<usize>,
+
+    /// Directory for sweep.csv, sweep.json, summary.csv and one subdirectory per run
+    #[clap(long, short, default_value = ".", value_parser)]
+    pub out: PathBuf,
+
+    /// Also write population metrics for every run (csv or json)
+    #[clap(long, value_parser)]
+    pub metrics: Option<MetricsFormat>,
+}
+
+#[derive(ClapArgs)]
+pub struct PhylogenyArgs {
+    /// lineage.csv written by a run with --lineage
+    #[clap(value_parser)]
+    pub lineage: PathBuf,
+
+    /// Only keep the blobs alive at the end and their ancestors
+    #[clap(long, action)]
+    pub pruned: bool,
+
+    /// Directory for phylogeny.nwk and phylogeny.json
+    #[clap(long, short, default_value = ".", value_parser)]
+    pub out: PathBuf,
+}
+
+#[derive(ClapArgs)]
+pub struct DisasmArgs {
+    /// Genomes in the blob2:e000.0c6b form
+    #[clap(value_parser)]
+    pub genomes: Vec<String>,
+
+    /// Also every genome in this file, one per line
+    #[clap(long, short, value_parser)]
+    pub file: Option<PathBuf>,
+}
+
+#[derive(ClapArgs)]
+pub struct DotArgs {
+    /// Genomes in the blob2:e000.0c6b form
+    #[clap(value_parser)]
+    pub genomes: Vec<String>,
+
+    /// Also every genome in this file, one per line
+    #[clap(long, short, value_parser)]
+    pub file: Option<PathBuf>,
+
+    /// Also every genome in this hall of fame
+    #[clap(long, value_parser)]
+    pub hall: Option<PathBuf>,
+
+    /// Write brain-<n>.dot per genome here instead of printing them all
+    #[clap(long, short, value_parser)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(ClapArgs)]
+pub struct AsmArgs {
+    /// Files with one network each, see chemotaxis.brain
+    #[clap(required = true, value_parser)]
+    pub files: Vec<PathBuf>,
+}
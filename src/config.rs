@@ -0,0 +1,496 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::prelude::App;
+use serde::{Deserialize, Serialize};
+
+use crate::{genes::Crossover, metrics::MetricsFormat};
+
+// Every tunable number of the sim, so an experiment can be a file instead of a fork.
+// Missing sections and fields fall back to the defaults below, unknown ones are errors
+// so typos don't silently do nothing
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub world: WorldConfig,
+    pub physics: PhysicsConfig,
+    pub food: FoodConfig,
+    pub blobs: BlobConfig,
+    pub genes: GeneConfig,
+    pub brain: BrainConfig,
+    pub metrics: MetricsConfig,
+    pub hall_of_fame: HallConfig,
+    pub lineage: LineageConfig,
+    pub species: SpeciesConfig,
+}
+
+// size of the world when running headless, and of the window otherwise
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    pub width: f32,
+    pub height: f32,
+}
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            width: 1280.,
+            height: 720.,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsConfig {
+    // brownian kicks are a standard normal divided by this
+    pub brown_scale: f32,
+    // fraction of velocity kept every tick
+    pub drag: f32,
+}
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            brown_scale: 8.,
+            drag: 0.8,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoodConfig {
+    pub min_food: u32,
+    // most food spawned in one go
+    pub spawn_batch: u32,
+    pub nutrition: f32,
+    // chance per tick that a piece of food emits a chem
+    pub emit_rate: f64,
+    // ticks a chem lasts before dissolving
+    pub chem_life: u16,
+}
+impl Default for FoodConfig {
+    fn default() -> Self {
+        Self {
+            min_food: 64,
+            spawn_batch: 8,
+            nutrition: 33.33,
+            emit_rate: 0.1,
+            chem_life: 256,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlobConfig {
+    pub min_blobs: u32,
+    pub start_energy: f32,
+    // distances are compared squared, so are these
+    pub eat_radius_sq: f32,
+    pub sense_radius_sq: f32,
+    // energy lost every tick just for being alive
+    pub metabolic_cost: f32,
+    // energy lost per tick is move_cost * (|x| + |y|)^2
+    pub move_cost: f32,
+    // reproduce output has to be above this to have any chance of replicating
+    pub reproduce_threshold: f32,
+    // blobs at or below this lose everything when trying to replicate
+    pub min_reproduce_energy: f32,
+    // how close a partner has to be when mating
+    pub mate_radius_sq: f32,
+}
+impl Default for BlobConfig {
+    fn default() -> Self {
+        Self {
+            min_blobs: 32,
+            start_energy: 100.,
+            eat_radius_sq: 10.,
+            sense_radius_sq: 100.,
+            metabolic_cost: 0.001,
+            move_cost: 0.2,
+            reproduce_threshold: 0.3,
+            min_reproduce_energy: 10.,
+            mate_radius_sq: 100.,
+        }
+    }
+}
+
+// rates of each mutation operator when replicating, see mutation.rs
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneConfig {
+    // chance per 16 bit block of flipping a bit
+    pub mut_rate: f64,
+    // chance per synapse gene of nudging its weight
+    pub weight_rate: f64,
+    // standard deviation of a weight nudge, weights go from -4 to 4
+    pub weight_sigma: f32,
+    // chance per synapse gene of connecting one end somewhere else
+    pub rewire_rate: f64,
+    // chance of gaining or losing an internal node
+    pub internal_rate: f64,
+    // chance of copying one synapse gene
+    pub dup_rate: f64,
+    // chance of losing one synapse gene
+    pub del_rate: f64,
+    // genomes don't grow past this many synapse genes
+    pub max_synapses: u32,
+    // scale every rate above by the one encoded in each genome's header
+    pub evolve_rate: bool,
+    // blobs need a partner to replicate when set, and the child mixes both genomes
+    pub crossover: Option<Crossover>,
+}
+impl GeneConfig {
+    // Config::validate checks this too, Genes::replicate checks it for library
+    // callers that build a GeneConfig themselves
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        probability("genes.mut_rate", self.mut_rate)?;
+        probability("genes.weight_rate", self.weight_rate)?;
+        non_negative("genes.weight_sigma", self.weight_sigma)?;
+        probability("genes.rewire_rate", self.rewire_rate)?;
+        probability("genes.internal_rate", self.internal_rate)?;
+        probability("genes.dup_rate", self.dup_rate)?;
+        probability("genes.del_rate", self.del_rate)
+    }
+}
+impl Default for GeneConfig {
+    fn default() -> Self {
+        Self {
+            mut_rate: 0.001,
+            weight_rate: 0.,
+            weight_sigma: 0.25,
+            rewire_rate: 0.,
+            internal_rate: 0.,
+            dup_rate: 0.001,
+            del_rate: 0.001,
+            max_synapses: 64,
+            evolve_rate: false,
+            crossover: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrainConfig {
+    // keep what internal neurons summed from one tick to the next
+    pub recurrent: bool,
+    // fraction of it lost every tick, 1 forgets everything like without recurrent.
+    // 0 isn't allowed, the sums would grow without end
+    pub leak: f32,
+}
+impl Default for BrainConfig {
+    fn default() -> Self {
+        Self {
+            recurrent: false,
+            leak: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    // ticks between samples
+    pub interval: u64,
+    // no metrics are written without a format
+    pub format: Option<MetricsFormat>,
+}
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            interval: 60,
+            format: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HallConfig {
+    // genomes kept for each criterion
+    pub size: usize,
+    // ticks between writing it out, it is also written when the run ends
+    pub save_every: u64,
+}
+impl Default for HallConfig {
+    fn default() -> Self {
+        Self {
+            size: 10,
+            save_every: 3600,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LineageConfig {
+    // write every birth and death to <out>/lineage.csv
+    pub log: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeciesConfig {
+    // ticks between censuses
+    pub interval: u64,
+    // most bits a genome can differ from a species' representative and still belong to it
+    pub threshold: u32,
+    // write every census to <out>/species.csv
+    pub log: bool,
+}
+impl Default for SpeciesConfig {
+    fn default() -> Self {
+        Self {
+            interval: 600,
+            threshold: 16,
+            log: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(ron::Error),
+    Invalid { field: &'static str, reason: String },
+    Unknown(String),
+}
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "could not parse config: {}", e),
+            ConfigError::Invalid { field, reason } => write!(f, "{} {}", field, reason),
+            ConfigError::Unknown(key) => write!(f, "{} is not a numeric parameter", key),
+        }
+    }
+}
+impl std::error::Error for ConfigError {}
+
+pub(crate) fn invalid(field: &'static str, reason: &str) -> ConfigError {
+    ConfigError::Invalid {
+        field,
+        reason: reason.to_string(),
+    }
+}
+
+fn positive(field: &'static str, v: f32) -> Result<(), ConfigError> {
+    if v.is_finite() && v > 0. {
+        Ok(())
+    } else {
+        Err(invalid(field, "must be a positive number"))
+    }
+}
+
+pub(crate) fn non_negative(field: &'static str, v: f32) -> Result<(), ConfigError> {
+    if v.is_finite() && v >= 0. {
+        Ok(())
+    } else {
+        Err(invalid(field, "must not be negative"))
+    }
+}
+
+fn probability(field: &'static str, v: f64) -> Result<(), ConfigError> {
+    if (0.0..=1.0).contains(&v) {
+        Ok(())
+    } else {
+        Err(invalid(field, "must be between 0 and 1"))
+    }
+}
+
+// integer fields only take whole numbers that fit
+fn whole(key: &str, v: f64, max: u64) -> Result<u64, ConfigError> {
+    if v.fract() == 0. && v >= 0. && v <= max as f64 {
+        Ok(v as u64)
+    } else {
+        Err(ConfigError::Invalid {
+            field: "value",
+            reason: format!("{} for {} must be a whole number up to {}", v, key, max),
+        })
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Config::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let config: Config = ron::from_str(text).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        positive("world.width", self.world.width)?;
+        positive("world.height", self.world.height)?;
+
+        positive("physics.brown_scale", self.physics.brown_scale)?;
+        probability("physics.drag", self.physics.drag as f64)?;
+
+        if self.food.spawn_batch == 0 {
+            return Err(invalid("food.spawn_batch", "must be at least 1"));
+        }
+        non_negative("food.nutrition", self.food.nutrition)?;
+        probability("food.emit_rate", self.food.emit_rate)?;
+
+        non_negative("blobs.start_energy", self.blobs.start_energy)?;
+        non_negative("blobs.eat_radius_sq", self.blobs.eat_radius_sq)?;
+        non_negative("blobs.sense_radius_sq", self.blobs.sense_radius_sq)?;
+        non_negative("blobs.metabolic_cost", self.blobs.metabolic_cost)?;
+        non_negative("blobs.move_cost", self.blobs.move_cost)?;
+        // the output is a sigmoid, anything at or above 1 can never replicate
+        if !(0.0..1.0).contains(&self.blobs.reproduce_threshold) {
+            return Err(invalid(
+                "blobs.reproduce_threshold",
+                "must be at least 0 and below 1",
+            ));
+        }
+        non_negative(
+            "blobs.min_reproduce_energy",
+            self.blobs.min_reproduce_energy,
+        )?;
+        non_negative("blobs.mate_radius_sq", self.blobs.mate_radius_sq)?;
+
+        self.genes.validate()?;
+        probability("brain.leak", self.brain.leak as f64)?;
+        if self.brain.leak == 0. {
+            return Err(invalid("brain.leak", "must be above 0"));
+        }
+
+        if self.metrics.interval == 0 {
+            return Err(invalid("metrics.interval", "must be at least 1"));
+        }
+        if self.hall_of_fame.save_every == 0 {
+            return Err(invalid("hall_of_fame.save_every", "must be at least 1"));
+        }
+        if self.species.interval == 0 {
+            return Err(invalid("species.interval", "must be at least 1"));
+        }
+        Ok(())
+    }
+
+    // Sets one numeric parameter by its dotted name, e.g. "genes.mut_rate".
+    // Doesn't validate, call validate() once everything is set
+    pub fn set(&mut self, key: &str, v: f64) -> Result<(), ConfigError> {
+        match key {
+            "world.width" => self.world.width = v as f32,
+            "world.height" => self.world.height = v as f32,
+            "physics.brown_scale" => self.physics.brown_scale = v as f32,
+            "physics.drag" => self.physics.drag = v as f32,
+            "food.min_food" => self.food.min_food = whole(key, v, u32::MAX as u64)? as u32,
+            "food.spawn_batch" => self.food.spawn_batch = whole(key, v, u32::MAX as u64)? as u32,
+            "food.nutrition" => self.food.nutrition = v as f32,
+            "food.emit_rate" => self.food.emit_rate = v,
+            "food.chem_life" => self.food.chem_life = whole(key, v, u16::MAX as u64)? as u16,
+            "blobs.min_blobs" => self.blobs.min_blobs = whole(key, v, u32::MAX as u64)? as u32,
+            "blobs.start_energy" => self.blobs.start_energy = v as f32,
+            "blobs.eat_radius_sq" => self.blobs.eat_radius_sq = v as f32,
+            "blobs.sense_radius_sq" => self.blobs.sense_radius_sq = v as f32,
+            "blobs.metabolic_cost" => self.blobs.metabolic_cost = v as f32,
+            "blobs.move_cost" => self.blobs.move_cost = v as f32,
+            "blobs.reproduce_threshold" => self.blobs.reproduce_threshold = v as f32,
+            "blobs.min_reproduce_energy" => self.blobs.min_reproduce_energy = v as f32,
+            "blobs.mate_radius_sq" => self.blobs.mate_radius_sq = v as f32,
+            "genes.mut_rate" => self.genes.mut_rate = v,
+            "genes.weight_rate" => self.genes.weight_rate = v,
+            "genes.weight_sigma" => self.genes.weight_sigma = v as f32,
+            "genes.rewire_rate" => self.genes.rewire_rate = v,
+            "genes.internal_rate" => self.genes.internal_rate = v,
+            "genes.dup_rate" => self.genes.dup_rate = v,
+            "genes.del_rate" => self.genes.del_rate = v,
+            "genes.max_synapses" => {
+                self.genes.max_synapses = whole(key, v, u32::MAX as u64)? as u32
+            }
+            "genes.evolve_rate" => self.genes.evolve_rate = whole(key, v, 1)? == 1,
+            "brain.recurrent" => self.brain.recurrent = whole(key, v, 1)? == 1,
+            "brain.leak" => self.brain.leak = v as f32,
+            "metrics.interval" => self.metrics.interval = whole(key, v, u64::MAX)?,
+            "hall_of_fame.size" => {
+                self.hall_of_fame.size = whole(key, v, u32::MAX as u64)? as usize
+            }
+            "hall_of_fame.save_every" => self.hall_of_fame.save_every = whole(key, v, u64::MAX)?,
+            "lineage.log" => self.lineage.log = whole(key, v, 1)? == 1,
+            "species.interval" => self.species.interval = whole(key, v, u64::MAX)?,
+            "species.threshold" => {
+                self.species.threshold = whole(key, v, u32::MAX as u64)? as u32
+            }
+            "species.log" => self.species.log = whole(key, v, 1)? == 1,
+            _ => return Err(ConfigError::Unknown(key.to_string())),
+        }
+        Ok(())
+    }
+
+    // each section becomes its own resource so systems only ask for what they use
+    pub fn insert_resources(&self, app: &mut App) {
+        app.insert_resource(self.clone())
+            .insert_resource(self.world.clone())
+            .insert_resource(self.physics.clone())
+            .insert_resource(self.food.clone())
+            .insert_resource(self.blobs.clone())
+            .insert_resource(self.genes.clone())
+            .insert_resource(self.brain.clone())
+            .insert_resource(self.metrics.clone())
+            .insert_resource(self.hall_of_fame.clone())
+            .insert_resource(self.lineage.clone())
+            .insert_resource(self.species.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, ConfigError};
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn partial_file_keeps_defaults() {
+        let config = Config::parse("(food: (min_food: 10), genes: (mut_rate: 0.01))").unwrap();
+        assert_eq!(config.food.min_food, 10);
+        assert_eq!(config.food.spawn_batch, 8);
+        assert_eq!(config.genes.mut_rate, 0.01);
+        assert_eq!(config.blobs.min_blobs, 32);
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        match Config::parse("(genes: (mut_rate: 1.5))") {
+            Err(ConfigError::Invalid { field, .. }) => assert_eq!(field, "genes.mut_rate"),
+            _ => panic!("mut_rate above 1 should be rejected"),
+        }
+        assert!(matches!(
+            Config::parse("(world: (width: 0.0))"),
+            Err(ConfigError::Invalid { .. })
+        ));
+        assert!(matches!(
+            Config::parse("(brain: (recurrent: true, leak: 0.0))"),
+            Err(ConfigError::Invalid {
+                field: "brain.leak",
+                ..
+            })
+        ));
+        assert!(matches!(
+            Config::parse("(food: (nutrishun: 1))"),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn set_by_name() {
+        let mut config = Config::default();
+        config.set("genes.mut_rate", 0.01).unwrap();
+        config.set("food.min_food", 12.).unwrap();
+        assert_eq!(config.genes.mut_rate, 0.01);
+        assert_eq!(config.food.min_food, 12);
+        assert!(config.set("food.min_food", 1.5).is_err());
+        assert!(matches!(
+            config.set("genes.mutrate", 0.1),
+            Err(ConfigError::Unknown(_))
+        ));
+    }
+}
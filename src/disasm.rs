@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::{
+    genes::Genes,
+    network::{DecodedSynapse, Node, SynapseKind, INPUT_NAMES, OUTPUT_NAMES},
+};
+
+// One synapse gene and where Network::new puts it, None if it was dropped
+pub struct SynapseLine {
+    pub gene: u16,
+    pub synapse: DecodedSynapse,
+    pub kind: Option<SynapseKind>,
+}
+
+// What Network::new makes of a genome. Prints as a listing with one synapse per line,
+// dropped synapses and everything else commented out with #
+pub struct Disassembly {
+    pub genes: Genes,
+    pub n_internal: usize,
+    pub synapses: Vec<SynapseLine>,
+}
+
+pub fn disassemble(genes: &Genes) -> Disassembly {
+    let n_internal = (genes.header >> 12) as usize;
+    Disassembly {
+        genes: genes.clone(),
+        n_internal,
+        synapses: genes
+            .synapses
+            .iter()
+            .map(|gene| {
+                let synapse = DecodedSynapse::new(*gene);
+                SynapseLine {
+                    gene: *gene,
+                    synapse,
+                    kind: synapse.kind(n_internal),
+                }
+            })
+            .collect(),
+    }
+}
+
+impl Disassembly {
+    // whether any synapse that made it into the network starts or ends at the node
+    pub fn is_connected(&self, node: Node) -> bool {
+        self.synapses
+            .iter()
+            .any(|line| line.kind.is_some() && (line.synapse.from == node || line.synapse.to == node))
+    }
+
+    fn write_used(
+        &self,
+        f: &mut fmt::Formatter,
+        what: &str,
+        nodes: impl Iterator<Item = (Node, String)>,
+    ) -> fmt::Result {
+        let (used, unused): (Vec<_>, Vec<_>) = nodes.partition(|(node, _)| self.is_connected(*node));
+        let names = |nodes: Vec<(Node, String)>| {
+            let names: Vec<String> = nodes.into_iter().map(|(_, name)| name).collect();
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        };
+        writeln!(
+            f,
+            "# {} connected: {}; not connected: {}",
+            what,
+            names(used),
+            names(unused)
+        )
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# {}", self.genes)?;
+        writeln!(f, "internal {}", self.n_internal)?;
+        writeln!(
+            f,
+            "rate {}  # mutation rates x{}",
+            self.genes.header & 0xff,
+            self.genes.mut_scale()
+        )?;
+        for line in &self.synapses {
+            let syn = &line.synapse;
+            let text = format!("{} -> {} w={}", syn.from, syn.to, syn.weight);
+            match line.kind {
+                Some(_) => writeln!(f, "{}  # {:04x}", text, line.gene)?,
+                None => {
+                    let missing: Vec<String> = [syn.from, syn.to]
+                        .iter()
+                        .filter(|node| !node.exists(self.n_internal))
+                        .map(|node| node.to_string())
+                        .collect();
+                    writeln!(
+                        f,
+                        "# {}  # {:04x} dropped, no {}",
+                        text,
+                        line.gene,
+                        missing.join(" or ")
+                    )?;
+                }
+            }
+        }
+        self.write_used(
+            f,
+            "inputs",
+            INPUT_NAMES
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (Node::Input(i as u8), name.to_string())),
+        )?;
+        self.write_used(
+            f,
+            "internal",
+            (0..self.n_internal).map(|i| (Node::Internal(i as u8), i.to_string())),
+        )?;
+        self.write_used(
+            f,
+            "outputs",
+            OUTPUT_NAMES
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (Node::Output(i as u8), name.to_string())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{disasm::disassemble, genes::Genes};
+
+    #[test]
+    fn lists_kept_and_dropped() {
+        // 4 internal, then internal:2 -> output:move-y and input:chem-x -> internal:5
+        let genes = Genes {
+            header: 0x4080,
+            synapses: vec![0xa120, 0x0dff],
+        };
+        let text = disassemble(&genes).to_string();
+        assert_eq!(
+            text,
+            "# blob2:4080.a120.0dff\n\
+            internal 4\n\
+            rate 128  # mutation rates x1\n\
+            internal:2 -> output:move-y w=1  # a120\n\
+            # input:chem-x -> internal:5 w=-0.03125  # 0dff dropped, no internal:5\n\
+            # inputs connected: none; not connected: chem-x, chem-y, energy, oscillator\n\
+            # internal connected: 2; not connected: 0, 1, 3\n\
+            # outputs connected: move-y; not connected: move-x, consume, reproduce\n"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt::Write;
+
+use crate::network::{Network, Node, INPUT_NAMES, OUTPUT_NAMES};
+
+// weights decode to -4..4
+const MAX_WEIGHT: f32 = 4.;
+
+fn node_id(node: Node) -> String {
+    match node {
+        Node::Input(i) => format!("in{}", i),
+        Node::Internal(i) => format!("int{}", i),
+        Node::Output(i) => format!("out{}", i),
+    }
+}
+
+// blue for excitatory, red for inhibitory, stronger weights are more opaque
+fn edge_color(weight: f32) -> String {
+    let alpha = 0x40 + ((weight.abs() / MAX_WEIGHT).min(1.) * 191.) as u8;
+    if weight >= 0. {
+        format!("#2060c0{:02x}", alpha)
+    } else {
+        format!("#c03020{:02x}", alpha)
+    }
+}
+
+// Graphviz DOT of a network, inputs on the left, outputs on the right and the
+// internal neurons any synapse touches in between. Every edge is labelled with its
+// weight, `label` goes under the graph (a genome, say)
+pub fn network_dot(net: &Network, name: &str, label: Option<&str>) -> String {
+    let synapses = net.synapses();
+    let mut internal: Vec<Node> = synapses
+        .iter()
+        .flat_map(|(from, to, _)| [*from, *to])
+        .filter(|node| matches!(node, Node::Internal(_)))
+        .collect();
+    internal.sort();
+    internal.dedup();
+
+    let mut dot = String::new();
+    let _ = writeln!(dot, "digraph \"{}\" {{", name);
+    let _ = writeln!(dot, "    rankdir=LR;");
+    if let Some(label) = label {
+        let _ = writeln!(dot, "    label=\"{}\";", label);
+    }
+    let _ = writeln!(dot, "    node [fontname=\"Helvetica\"];");
+    let _ = writeln!(dot, "    {{ rank=source;");
+    for (i, name) in INPUT_NAMES.iter().enumerate() {
+        let _ = writeln!(
+            dot,
+            "        {} [label=\"{}\", shape=box];",
+            node_id(Node::Input(i as u8)),
+            name
+        );
+    }
+    let _ = writeln!(dot, "    }}");
+    for node in &internal {
+        if let Node::Internal(i) = node {
+            let _ = writeln!(dot, "    {} [label=\"{}\", shape=circle];", node_id(*node), i);
+        }
+    }
+    let _ = writeln!(dot, "    {{ rank=sink;");
+    for (i, name) in OUTPUT_NAMES.iter().enumerate() {
+        let _ = writeln!(
+            dot,
+            "        {} [label=\"{}\", shape=doublecircle];",
+            node_id(Node::Output(i as u8)),
+            name
+        );
+    }
+    let _ = writeln!(dot, "    }}");
+    for (from, to, weight) in synapses {
+        let _ = writeln!(
+            dot,
+            "    {} -> {} [label=\"{}\", color=\"{}\", penwidth={:.2}];",
+            node_id(from),
+            node_id(to),
+            weight,
+            edge_color(weight),
+            0.5 + 2. * weight.abs() / MAX_WEIGHT
+        );
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{dot::network_dot, genes::Genes, network::Network};
+
+    #[test]
+    fn draws_kept_synapses() {
+        // see disasm.rs, the second synapse points at an internal neuron that doesn't exist
+        let genes = Genes {
+            header: 0x4080,
+            synapses: vec![0xa120, 0x0dff],
+        };
+        let dot = network_dot(&Network::new(&genes), "brain", Some("blob2:4080.a120.0dff"));
+        assert!(dot.starts_with("digraph \"brain\" {\n"));
+        assert!(dot.contains("in2 [label=\"energy\", shape=box];"));
+        assert!(dot.contains("out3 [label=\"reproduce\", shape=doublecircle];"));
+        assert!(dot.contains("int2 [label=\"2\", shape=circle];"));
+        assert!(dot.contains("int2 -> out1 [label=\"1\", color=\"#2060c06f\", penwidth=1.00];"));
+        assert!(!dot.contains("int5"));
+        assert_eq!(dot.matches("->").count(), 1);
+    }
+}
@@ -1,66 +1,65 @@
+use std::collections::BTreeSet;
+
 use bevy::{
-    core::FixedTimestep,
-    math::{Vec2, Vec3},
+    math::Vec3,
     prelude::{
-        App, Color, Commands, Component, Entity, Plugin, Query, Res, ResMut, SystemSet, Transform,
+        App, Commands, Component, Entity, EventWriter, ParallelSystemDescriptorCoercion, Plugin,
+        Query, Res, ResMut, SystemLabel, SystemSet, Transform, TransformBundle,
     },
-    sprite::{Sprite, SpriteBundle},
-    utils::HashSet,
 };
 use rand::Rng;
 
-use crate::{Acceleration, Chem, Stages, Velocity, WinSize};
+use crate::{
+    config::FoodConfig,
+    once_per_second,
+    rng::{SimRng, Stream},
+    Acceleration, Chem, SimLabel, Stages, Tick, Velocity, WinSize,
+};
 
 #[derive(Component)]
 pub struct Food {
     pub nutriton: f32,
-    chem_id: u8,
-    emit_life: f64,
+    pub chem_id: u8,
+    pub emit_life: f64,
 }
-impl Default for Food {
-    fn default() -> Self {
+impl Food {
+    pub fn new(config: &FoodConfig) -> Self {
         Food {
-            nutriton: 33.33,
+            nutriton: config.nutrition,
             chem_id: 1u8,
-            emit_life: 0.1,
+            emit_life: config.emit_rate,
         }
     }
 }
+#[derive(Default)]
+pub struct CurFood(pub u32);
 
-struct MinFood(u32);
-impl Default for MinFood {
-    fn default() -> Self {
-        Self(64)
-    }
-}
-struct CurFood(u32);
-impl Default for CurFood {
-    fn default() -> Self {
-        Self(0)
-    }
-}
+// Ordered so food is always despawned in the same order. Freed entity ids are handed
+// out again and entity_stream is keyed on them, so a hash set's per process order
+// would give blobs different random streams from run to run
+#[derive(Default)]
+pub struct EatenFood(pub BTreeSet<Entity>);
 
-pub struct EatenFood(pub HashSet<Entity>);
-impl Default for EatenFood {
-    fn default() -> Self {
-        Self(HashSet::new())
-    }
-}
+// Pieces of food eaten this tick
+pub struct FoodEaten(pub u32);
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct RemoveFood;
 
 pub struct FoodPlugin;
 impl Plugin for FoodPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(CurFood::default())
-            .insert_resource(MinFood::default())
             .insert_resource(EatenFood::default())
+            .add_event::<FoodEaten>()
             .add_system_set_to_stage(
                 Stages::FoodStage,
                 SystemSet::new()
-                    .with_run_criteria(FixedTimestep::step(1.0))
-                    .with_system(spawn_food),
+                    .with_run_criteria(once_per_second)
+                    .with_system(spawn_food.after(RemoveFood)),
             )
-            .add_system_to_stage(Stages::FoodStage, remove_food)
-            .add_system(emit_chems)
+            .add_system_to_stage(Stages::FoodStage, remove_food.label(RemoveFood))
+            .add_system(emit_chems.label(SimLabel::Chems).after(SimLabel::Drift))
             .add_system(dissolve_chems);
     }
 }
@@ -69,30 +68,23 @@ impl Plugin for FoodPlugin {
 fn spawn_food(
     mut commands: Commands,
     mut cur_food: ResMut<CurFood>,
-    min_food: Res<MinFood>,
+    config: Res<FoodConfig>,
     win: Res<WinSize>,
+    rng: Res<SimRng>,
+    tick: Res<Tick>,
 ) {
-    let mut r = rand::thread_rng();
-    let mut ctr = 0u8; // only spawn eight in one go
-    while cur_food.0 < min_food.0 && ctr < 8 {
+    let mut r = rng.stream(Stream::SpawnFood, tick.0);
+    let mut ctr = 0; // only spawn a few in one go
+    while cur_food.0 < config.min_food && ctr < config.spawn_batch {
         commands
-            .spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    color: Color::rgb(0., 1., 0.),
-                    custom_size: Some(Vec2::new(3., 3.)),
-                    ..Default::default()
-                },
-                transform: Transform {
-                    translation: Vec3::new(
-                        r.gen_range(-win.w..win.w),
-                        r.gen_range(-win.h..win.h),
-                        1.,
-                    ),
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(Food::default())
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(Vec3::new(
+                    r.gen_range(-win.w..win.w),
+                    r.gen_range(-win.h..win.h),
+                    1.,
+                )),
+            ))
+            .insert(Food::new(&config))
             .insert(Velocity::default())
             .insert(Acceleration::default());
         cur_food.0 += 1;
@@ -101,26 +93,23 @@ fn spawn_food(
 }
 
 // Emits chemicals that blobs can perceive
-fn emit_chems(mut commands: Commands, query: Query<(&Transform, &Food)>) {
-    let mut r = rand::thread_rng();
+fn emit_chems(
+    mut commands: Commands,
+    query: Query<(&Transform, &Food)>,
+    config: Res<FoodConfig>,
+    rng: Res<SimRng>,
+    tick: Res<Tick>,
+) {
+    let mut r = rng.stream(Stream::EmitChems, tick.0);
     query.for_each(|(trans, food)| {
         if r.gen_bool(food.emit_life) {
             commands
-                .spawn_bundle(SpriteBundle {
-                    sprite: Sprite {
-                        color: Color::rgba_u8(food.chem_id, food.chem_id, food.chem_id, 123u8),
-                        custom_size: Some(Vec2::new(1., 1.)),
-                        ..Default::default()
-                    },
-                    transform: Transform {
-                        translation: trans.translation.clone(),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
+                .spawn_bundle(TransformBundle::from_transform(
+                    Transform::from_translation(trans.translation),
+                ))
                 .insert(Chem {
-                    // id: food.chem_id,
-                    dissolve_life: 256u16,
+                    id: food.chem_id,
+                    dissolve_life: config.chem_life,
                 })
                 .insert(Velocity::default())
                 .insert(Acceleration::default());
@@ -142,10 +131,14 @@ fn remove_food(
     mut commands: Commands,
     mut cur_food: ResMut<CurFood>,
     mut eaten_food: ResMut<EatenFood>,
+    mut eaten: EventWriter<FoodEaten>,
 ) {
     for food in eaten_food.0.iter() {
         commands.entity(*food).despawn();
         cur_food.0 -= 1;
     }
+    if !eaten_food.0.is_empty() {
+        eaten.send(FoodEaten(eaten_food.0.len() as u32));
+    }
     eaten_food.0.clear();
 }
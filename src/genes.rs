@@ -1,37 +1,333 @@
+use std::{error::Error, fmt, fs, path::Path, str::FromStr};
+
 use bevy::prelude::Component;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ConfigError, GeneConfig},
+    migrate,
+    mutation::Mutation,
+};
+
+// TODO: remember to turn i8 weights into i16s
+type Gene = u16;
 
-const MUT_RATE: f64 = 0.001;
+// text genomes start with blob<version>:, see migrate.rs for the versions
+const TEXT_TAG: &str = "blob";
 
-// TODO: turn this into a vector or array to support more genes
-// also remember to turn i8 weights into i16s
-type Gene = u128;
+// synapses in the original fixed size genome
+const LEGACY_SYNAPSES: usize = 7;
 
-#[derive(Clone, Component)]
+// A header followed by any number of synapse genes, see Network::new for the layout
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Component, Serialize, Deserialize,
+)]
 pub struct Genes {
-    pub gene: Gene,
+    pub header: Gene,
+    pub synapses: Vec<Gene>,
+}
+
+// How two parents' genomes are combined when mating
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Crossover {
+    // one parent's genes up to a random point, the other's after it
+    SinglePoint,
+    // every bit from either parent
+    Uniform,
+    // every whole gene from either parent
+    PerSynapse,
 }
-impl Default for Genes {
-    fn default() -> Self {
+
+impl Genes {
+    // starting genomes keep the old size: a header and 7 synapses
+    pub fn random(r: &mut impl Rng) -> Genes {
+        Genes::from_u128(r.gen())
+    }
+
+    // the old single u128 genome, header in the top 16 bits and synapses below it
+    pub fn from_u128(gene: u128) -> Genes {
+        let block = |i: usize| (gene >> ((LEGACY_SYNAPSES - i) * 16)) as Gene;
         Genes {
-            gene: rand::thread_rng().gen::<Gene>(),
+            header: block(0),
+            synapses: (1..=LEGACY_SYNAPSES).map(block).collect(),
         }
     }
+
+    // header then synapses
+    pub fn words(&self) -> impl Iterator<Item = Gene> + '_ {
+        std::iter::once(self.header).chain(self.synapses.iter().copied())
+    }
+
+    fn from_words(words: Vec<Gene>) -> Genes {
+        Genes {
+            header: words[0],
+            synapses: words[1..].to_vec(),
+        }
+    }
+
+    // Combines two parents, the header counts as the first gene.
+    // Genes past the end of the shorter parent are copied from the longer one
+    pub fn crossover(&self, other: &Genes, how: Crossover, r: &mut impl Rng) -> Genes {
+        let a: Vec<Gene> = self.words().collect();
+        let b: Vec<Gene> = other.words().collect();
+        let words = match how {
+            Crossover::SinglePoint => {
+                // the second parent's tail decides the length
+                let cut = r.gen_range(1..=a.len().min(b.len()));
+                a[..cut].iter().chain(&b[cut..]).copied().collect()
+            }
+            Crossover::Uniform | Crossover::PerSynapse => {
+                let len = if r.gen_bool(0.5) { a.len() } else { b.len() };
+                (0..len)
+                    .map(|i| match (a.get(i), b.get(i)) {
+                        (Some(&x), Some(&y)) if how == Crossover::Uniform => {
+                            let mask: Gene = r.gen();
+                            (x & mask) | (y & !mask)
+                        }
+                        (Some(&x), Some(&y)) => {
+                            if r.gen_bool(0.5) {
+                                x
+                            } else {
+                                y
+                            }
+                        }
+                        (Some(&x), None) | (None, Some(&x)) => x,
+                        (None, None) => unreachable!("i is below one of the lengths"),
+                    })
+                    .collect()
+            }
+        };
+        Genes::from_words(words)
+    }
+
+    // Bits that differ, header included. Genes past the end of the shorter genome
+    // count as entirely different
+    pub fn distance(&self, other: &Genes) -> u32 {
+        let same_len = self.synapses.len().min(other.synapses.len());
+        let extra = self.synapses.len().max(other.synapses.len()) - same_len;
+        self.words()
+            .zip(other.words())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum::<u32>()
+            + extra as u32 * Gene::BITS
+    }
+
+    // The low 8 bits of the header are this lineage's mutation rate as a multiplier
+    // of the configured rates, from 1/16 at 0 through 1 at 128 to almost 16 at 255
+    pub fn mut_scale(&self) -> f64 {
+        2f64.powf(((self.header & 0xff) as f64 - 128.) / 32.)
+    }
+
+    // A copy with every mutation operator applied at its configured rate,
+    // scaled by mut_scale if the rate evolves. Errors if the config is invalid
+    pub fn replicate(&self, r: &mut impl Rng, config: &GeneConfig) -> Result<Genes, ConfigError> {
+        config.validate()?;
+        let scale = if config.evolve_rate {
+            self.mut_scale()
+        } else {
+            1.
+        };
+        let mut child = self.clone();
+        for op in Mutation::ALL {
+            op.apply(&mut child, r, config, scale)?;
+        }
+        Ok(child)
+    }
 }
 
-impl Genes {
-    pub fn replicate(&self) -> Genes {
-        let mut r = rand::thread_rng();
-        let mut gene = self.gene.clone();
-        let mut mutate = |start: i32, end: i32| {
-            if r.gen_bool(MUT_RATE) {
-                gene ^= 1u128 << r.gen_range(start..end);
+// Text form, a tag with the format version then hex words separated by dots, header first:
+// blob2:e000.0c6b.a180
+impl fmt::Display for Genes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}:{:04x}", TEXT_TAG, migrate::CURRENT, self.header)?;
+        for syn in &self.synapses {
+            write!(f, ".{:04x}", syn)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseGenesError {
+    // doesn't start with blob<version>:
+    MissingTag,
+    Version(u32),
+    BadGene(String),
+}
+impl fmt::Display for ParseGenesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseGenesError::MissingTag => {
+                write!(f, "genome should start with {}<version>:", TEXT_TAG)
+            }
+            ParseGenesError::Version(v) => write!(
+                f,
+                "genome format version {} is newer than this build understands ({})",
+                v,
+                migrate::CURRENT
+            ),
+            ParseGenesError::BadGene(gene) => {
+                write!(f, "{} is not a gene, expected 4 hex digits", gene)
             }
+        }
+    }
+}
+impl Error for ParseGenesError {}
+
+impl FromStr for Genes {
+    type Err = ParseGenesError;
+
+    // older versions are migrated to the current layout
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // bare numbers are genomes from before the text format, printed as a decimal u128
+        if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+            return migrate::from_version(0, s);
+        }
+        let (tag, body) = s.split_once(':').ok_or(ParseGenesError::MissingTag)?;
+        let version = tag
+            .strip_prefix(TEXT_TAG)
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or(ParseGenesError::MissingTag)?;
+        migrate::from_version(version, body)
+    }
+}
+
+impl Genes {
+    // dot separated hex words, header first
+    pub(crate) fn parse_words(body: &str) -> Result<Genes, ParseGenesError> {
+        let words = body
+            .split('.')
+            .map(|word| match word.len() {
+                4 => Gene::from_str_radix(word, 16)
+                    .map_err(|_| ParseGenesError::BadGene(word.to_string())),
+                _ => Err(ParseGenesError::BadGene(word.to_string())),
+            })
+            .collect::<Result<Vec<Gene>, _>>()?;
+        Ok(Genes::from_words(words))
+    }
+
+    // One genome per line, blank lines and lines starting with # are skipped
+    pub fn parse_list(text: &str) -> Result<Vec<Genes>, Box<dyn Error>> {
+        text.lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                line.parse()
+                    .map_err(|e| format!("line {}: {}", i + 1, e).into())
+            })
+            .collect()
+    }
+
+    pub fn read_list(path: &Path) -> Result<Vec<Genes>, Box<dyn Error>> {
+        Genes::parse_list(&fs::read_to_string(path)?)
+    }
+}
+
+// The genome most tests start from: 14 internal neurons, input:chem-x -> internal:4,
+// internal:2 -> output:move-y and 5 zero genes
+#[cfg(test)]
+pub(crate) fn test_genes() -> Genes {
+    Genes {
+        header: 0xe000,
+        synapses: vec![0x0c6b, 0xa180, 0, 0, 0, 0, 0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::{
+        config::GeneConfig,
+        genes::{test_genes, Crossover, Genes, ParseGenesError},
+    };
+
+    #[test]
+    fn replicate_grows_and_shrinks() {
+        let mut r = ChaCha8Rng::seed_from_u64(0);
+        let genes = test_genes();
+        assert_eq!(genes.header, 0b1110000000000000);
+        assert_eq!(genes.synapses.len(), 7);
+
+        let grow = GeneConfig {
+            mut_rate: 0.,
+            dup_rate: 1.,
+            del_rate: 0.,
+            max_synapses: 9,
+            ..Default::default()
         };
-        for i in 0..8 {
-            mutate(i * 16, (i + 1) * 16);
+        let mut child = genes.clone();
+        for _ in 0..5 {
+            child = child.replicate(&mut r, &grow).unwrap();
         }
+        assert_eq!(child.synapses.len(), 9);
+
+        let shrink = GeneConfig {
+            dup_rate: 0.,
+            del_rate: 1.,
+            ..grow
+        };
+        for _ in 0..20 {
+            child = child.replicate(&mut r, &shrink).unwrap();
+        }
+        assert!(child.synapses.is_empty());
+    }
 
-        Genes { gene }
+    #[test]
+    fn text_round_trip() {
+        let genes = test_genes();
+        let text = genes.to_string();
+        assert_eq!(text, "blob2:e000.0c6b.a180.0000.0000.0000.0000.0000");
+        assert_eq!(text.parse::<Genes>(), Ok(genes));
+
+        assert_eq!(
+            "blob9:e000".parse::<Genes>(),
+            Err(ParseGenesError::Version(9))
+        );
+        assert_eq!(
+            "e000.396b".parse::<Genes>(),
+            Err(ParseGenesError::MissingTag)
+        );
+        assert!("blob2:e000.3d6".parse::<Genes>().is_err());
+
+        let list = Genes::parse_list("# best so far\n\nblob2:e000.0c6b\n  blob2:0001\n").unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list[1].synapses.is_empty());
+        assert!(Genes::parse_list("blob2:e000\nnope").is_err());
+    }
+
+    #[test]
+    fn crossover_mixes_parents() {
+        let mut r = ChaCha8Rng::seed_from_u64(0);
+        let a = Genes {
+            header: 0,
+            synapses: vec![0; 4],
+        };
+        let b = Genes {
+            header: u16::MAX,
+            synapses: vec![u16::MAX; 8],
+        };
+        for how in [
+            Crossover::SinglePoint,
+            Crossover::Uniform,
+            Crossover::PerSynapse,
+        ] {
+            for _ in 0..20 {
+                let child = a.crossover(&b, how, &mut r);
+                assert!(child.synapses.len() == 4 || child.synapses.len() == 8);
+                // past the shorter parent everything comes from the longer one
+                assert!(child.synapses[4..].iter().all(|g| *g == u16::MAX));
+                if how == Crossover::SinglePoint {
+                    assert_eq!(child.header, 0);
+                }
+                if how == Crossover::PerSynapse {
+                    assert!(child.words().all(|g| g == 0 || g == u16::MAX));
+                }
+            }
+        }
     }
 }
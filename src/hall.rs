@@ -0,0 +1,254 @@
+use std::{error::Error, fs::File, io::BufWriter, path::Path};
+
+use bevy::{
+    app::AppExit,
+    log::{error, info},
+    prelude::{
+        App, CoreStage, EventReader, ParallelSystemDescriptorCoercion, Plugin, Query, Res, ResMut,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blob::Blob, config::HallConfig, genes::Genes, once_per_second, OutDir, SimLabel, Stages, Tick,
+};
+
+// One genome that made it in, with the blob that carried it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    // text form, so older files still load through the migrators
+    pub genome: String,
+    pub generation: u16,
+    pub age: f32,
+    pub offspring: u32,
+    pub food_eaten: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Criterion {
+    Generation,
+    Age,
+    Offspring,
+    Food,
+}
+impl Criterion {
+    pub const ALL: [Criterion; 4] = [
+        Criterion::Generation,
+        Criterion::Age,
+        Criterion::Offspring,
+        Criterion::Food,
+    ];
+
+    fn score(self, blob: &Blob) -> f32 {
+        match self {
+            Criterion::Generation => blob.generation as f32,
+            Criterion::Age => blob.age,
+            Criterion::Offspring => blob.offspring as f32,
+            Criterion::Food => blob.food_eaten as f32,
+        }
+    }
+
+    fn entry_score(self, entry: &Entry) -> f32 {
+        match self {
+            Criterion::Generation => entry.generation as f32,
+            Criterion::Age => entry.age,
+            Criterion::Offspring => entry.offspring as f32,
+            Criterion::Food => entry.food_eaten as f32,
+        }
+    }
+}
+
+// The best genomes of the run so far by each criterion, best first.
+// A genome is only listed once per criterion, with its best showing
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct HallOfFame {
+    pub generation: Vec<Entry>,
+    pub age: Vec<Entry>,
+    pub offspring: Vec<Entry>,
+    pub food: Vec<Entry>,
+}
+
+impl HallOfFame {
+    pub fn read(path: &Path) -> Result<HallOfFame, Box<dyn Error>> {
+        Ok(ron::de::from_reader(File::open(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let pretty = ron::ser::PrettyConfig::default();
+        ron::ser::to_writer_pretty(BufWriter::new(File::create(path)?), self, pretty)?;
+        Ok(())
+    }
+
+    pub fn list(&self, criterion: Criterion) -> &Vec<Entry> {
+        match criterion {
+            Criterion::Generation => &self.generation,
+            Criterion::Age => &self.age,
+            Criterion::Offspring => &self.offspring,
+            Criterion::Food => &self.food,
+        }
+    }
+
+    fn list_mut(&mut self, criterion: Criterion) -> &mut Vec<Entry> {
+        match criterion {
+            Criterion::Generation => &mut self.generation,
+            Criterion::Age => &mut self.age,
+            Criterion::Offspring => &mut self.offspring,
+            Criterion::Food => &mut self.food,
+        }
+    }
+
+    pub fn max_generation(&self) -> u16 {
+        self.generation.first().map(|e| e.generation).unwrap_or(0)
+    }
+
+    // Puts the blob in every list it beats the bottom of, returns whether it is
+    // now the highest generation
+    pub fn consider(&mut self, blob: &Blob, genes: &Genes, size: usize) -> bool {
+        let mut genome = None;
+        let mut top_generation = false;
+        for criterion in Criterion::ALL {
+            let score = criterion.score(blob);
+            let list = self.list_mut(criterion);
+            let beats_last = list.len() < size
+                || list
+                    .last()
+                    .is_some_and(|e| criterion.entry_score(e) < score);
+            if size == 0 || !beats_last {
+                continue;
+            }
+            let genome = genome.get_or_insert_with(|| genes.to_string());
+            match list.iter().position(|e| &e.genome == genome) {
+                Some(i) if criterion.entry_score(&list[i]) >= score => continue,
+                Some(i) => {
+                    list.remove(i);
+                }
+                None => {}
+            }
+            let i = list
+                .iter()
+                .position(|e| criterion.entry_score(e) < score)
+                .unwrap_or(list.len());
+            list.insert(
+                i,
+                Entry {
+                    genome: genome.clone(),
+                    generation: blob.generation,
+                    age: blob.age,
+                    offspring: blob.offspring,
+                    food_eaten: blob.food_eaten,
+                },
+            );
+            list.truncate(size);
+            top_generation |= criterion == Criterion::Generation && i == 0;
+        }
+        top_generation
+    }
+
+    // Every genome in any list, once, for seeding a new run
+    pub fn genomes(&self) -> Result<Vec<Genes>, Box<dyn Error>> {
+        let mut genomes: Vec<Genes> = Vec::new();
+        for criterion in Criterion::ALL {
+            for entry in self.list(criterion) {
+                let genes: Genes = entry.genome.parse()?;
+                if !genomes.contains(&genes) {
+                    genomes.push(genes);
+                }
+            }
+        }
+        Ok(genomes)
+    }
+}
+
+// Keeps the HallOfFame resource up to date and writes it to <out>/hall_of_fame.ron
+// every hall_of_fame.save_every ticks and when the app exits
+pub struct HallPlugin;
+impl Plugin for HallPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HallOfFame::default())
+            // after everything that changes blobs, so a seeded run keeps the same hall
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                induct.with_run_criteria(once_per_second),
+            )
+            .add_system_to_stage(CoreStage::First, save_hall.before(SimLabel::Tick))
+            .add_system_to_stage(Stages::FoodStage, save_hall_on_exit.after(SimLabel::Exit));
+    }
+}
+
+fn induct(mut hall: ResMut<HallOfFame>, config: Res<HallConfig>, query: Query<(&Blob, &Genes)>) {
+    query.for_each(|(blob, genes)| {
+        if hall.consider(blob, genes, config.size) {
+            info!(
+                "New highest gen blob! Gen: {} Genome: {}",
+                blob.generation, genes
+            );
+        }
+    });
+}
+
+fn write_hall(hall: &HallOfFame, out: &OutDir) {
+    let path = out.0.join("hall_of_fame.ron");
+    if let Err(e) = hall.write(&path) {
+        error!("Could not save hall of fame to {}: {}", path.display(), e);
+    }
+}
+
+fn save_hall(hall: Res<HallOfFame>, config: Res<HallConfig>, tick: Res<Tick>, out: Res<OutDir>) {
+    if tick.0 > 0 && tick.0 % config.save_every == 0 {
+        write_hall(&hall, &out);
+    }
+}
+
+fn save_hall_on_exit(mut exits: EventReader<AppExit>, hall: Res<HallOfFame>, out: Res<OutDir>) {
+    if exits.iter().count() > 0 {
+        write_hall(&hall, &out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        blob::Blob,
+        genes::Genes,
+        hall::{Criterion, HallOfFame},
+        network::Network,
+    };
+
+    fn blob(genes: &Genes, generation: u16, age: f32) -> Blob {
+        Blob {
+            energy: 1.,
+            age,
+            generation,
+            brain: Box::new(Network::new(genes)),
+            actions: Default::default(),
+            exhausted: false,
+            offspring: 0,
+            food_eaten: 0,
+            peak_energy: 1.,
+            id: 0,
+            parents: vec![],
+            born: 0,
+            birth_energy: 1.,
+        }
+    }
+
+    #[test]
+    fn keeps_the_best_once() {
+        let mut hall = HallOfFame::default();
+        let a = Genes::from_u128(1);
+        let b = Genes::from_u128(2);
+        let c = Genes::from_u128(3);
+        assert!(hall.consider(&blob(&a, 1, 5.), &a, 2));
+        assert!(hall.consider(&blob(&b, 3, 1.), &b, 2));
+        assert!(!hall.consider(&blob(&c, 2, 3.), &c, 2));
+        // a again, older but no deeper
+        assert!(!hall.consider(&blob(&a, 1, 9.), &a, 2));
+
+        let gens: Vec<u16> = hall.generation.iter().map(|e| e.generation).collect();
+        assert_eq!(gens, vec![3, 2]);
+        let ages: Vec<f32> = hall.list(Criterion::Age).iter().map(|e| e.age).collect();
+        assert_eq!(ages, vec![9., 3.]);
+        assert_eq!(hall.max_generation(), 3);
+        assert_eq!(hall.genomes().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,190 @@
+//! A relatively simple bacteria-inspired sim, as a set of Bevy plugins.
+//! Add `SimulationPlugin` next to `DefaultPlugins` (plus `SpritesPlugin` to see it)
+//! or `MinimalPlugins` to run it headless.
+
+// tick % n == 0 rather than u64::is_multiple_of, which needs Rust 1.87
+#![allow(clippy::manual_is_multiple_of)]
+
+use std::path::PathBuf;
+
+use bevy::{
+    app::AppExit,
+    ecs::schedule::ShouldRun,
+    log::info,
+    prelude::{
+        App, Component, CoreStage, EventWriter, ParallelSystemDescriptorCoercion, Plugin, Res,
+        ResMut, StageLabel, SystemLabel, SystemStage,
+    },
+};
+
+pub mod asm;
+pub mod batch;
+pub mod blob;
+pub mod brain;
+pub mod config;
+pub mod disasm;
+pub mod dot;
+pub mod food;
+pub mod genes;
+pub mod hall;
+pub mod lineage;
+pub mod metrics;
+pub mod migrate;
+pub mod mutation;
+pub mod network;
+pub mod phylogeny;
+pub mod physics;
+pub mod rng;
+pub mod snapshot;
+pub mod species;
+pub mod sprites;
+pub mod sweep;
+
+pub use blob::BlobPlugin;
+pub use brain::{Brain, BrainBuilder};
+pub use config::Config;
+pub use food::FoodPlugin;
+pub use genes::Genes;
+pub use hall::{HallOfFame, HallPlugin};
+pub use lineage::LineagePlugin;
+pub use metrics::MetricsPlugin;
+pub use network::Network;
+pub use physics::{brownian_drift, Acceleration, Velocity, WinSize};
+pub use snapshot::{Snapshot, SnapshotPlugin};
+pub use species::SpeciesPlugin;
+pub use sprites::SpritesPlugin;
+
+use rng::SimRng;
+
+#[derive(Component)]
+pub struct Chem {
+    // is also colour in shader
+    pub id: u8,
+    pub dissolve_life: u16,
+}
+
+// make a grid that will be drawn on by a shader
+
+// Frames simulated so far. Anything that should happen "every so often" counts
+// ticks rather than wall time, so runs don't depend on how fast the machine is
+#[derive(Default)]
+pub struct Tick(pub u64);
+
+const TICKS_PER_SEC: u64 = 60;
+
+pub fn once_per_second(tick: Res<Tick>) -> ShouldRun {
+    if tick.0 % TICKS_PER_SEC == 0 {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn advance_tick(mut tick: ResMut<Tick>) {
+    tick.0 += 1;
+}
+
+// Run ends once this many ticks have been fully simulated
+pub struct TickLimit(pub u64);
+
+fn stop_at_limit(tick: Res<Tick>, limit: Option<Res<TickLimit>>, mut exit: EventWriter<AppExit>) {
+    if let Some(limit) = limit {
+        if tick.0 >= limit.0 {
+            exit.send(AppExit);
+        }
+    }
+}
+
+// where anything the run writes ends up
+pub struct OutDir(pub PathBuf);
+
+// TODO:
+// - make entire 'screen' follow fluid dynamics to move everything
+// - turn spawn command into a closure, most of code is repeated. Maybe a macro
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
+pub enum Stages {
+    FoodStage,
+    BlobStage,
+}
+
+// Systems that spawn entities or share a resource are ordered explicitly,
+// otherwise the executor is free to shuffle them and seeded runs diverge
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub enum SimLabel {
+    // startup: filling the world from a snapshot
+    Restore,
+    Tick,
+    Drift,
+    Chems,
+    // sending AppExit at the tick limit
+    Exit,
+}
+
+// Everything the sim needs: config resources, stages, food, blobs, physics, snapshots,
+// metrics, the hall of fame, the lineage log and species.
+// The world starts out the configured size, SpritesPlugin resizes it to the window.
+// A Snapshot inserted before the plugin is restored at startup
+pub struct SimulationPlugin {
+    pub config: Config,
+    pub seed: u64,
+    pub out: PathBuf,
+}
+impl SimulationPlugin {
+    pub fn new(config: Config, seed: u64) -> Self {
+        Self {
+            config,
+            seed,
+            out: PathBuf::from("."),
+        }
+    }
+}
+impl Default for SimulationPlugin {
+    fn default() -> Self {
+        Self::new(Config::default(), rand::random())
+    }
+}
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        self.config.insert_resources(app);
+        // a BrainBuilder inserted before the plugin wins over the configured network
+        if app.world.get_resource::<BrainBuilder>().is_none() {
+            app.insert_resource(BrainBuilder::network(&self.config.brain));
+        }
+        // a restored world starts at the tick it was saved on, so the first update
+        // simulates the one after it
+        let tick = Tick(
+            app.world
+                .get_resource::<Snapshot>()
+                .map_or(0, |snap| snap.tick()),
+        );
+        app.insert_resource(WinSize {
+            w: self.config.world.width,
+            h: self.config.world.height,
+        })
+        .insert_resource(SimRng::new(self.seed))
+        .insert_resource(tick)
+        .insert_resource(OutDir(self.out.clone()))
+        .add_stage(Stages::BlobStage, SystemStage::parallel())
+        .add_stage_after(
+            Stages::BlobStage,
+            Stages::FoodStage,
+            SystemStage::parallel(),
+        )
+        .add_plugin(FoodPlugin)
+        .add_plugin(BlobPlugin)
+        .add_plugin(SnapshotPlugin)
+        .add_plugin(MetricsPlugin)
+        .add_plugin(HallPlugin)
+        .add_plugin(LineagePlugin)
+        .add_plugin(SpeciesPlugin)
+        .add_startup_system(announce_seed)
+        .add_system_to_stage(CoreStage::First, advance_tick.label(SimLabel::Tick))
+        .add_system(brownian_drift.label(SimLabel::Drift))
+        .add_system_to_stage(Stages::FoodStage, stop_at_limit.label(SimLabel::Exit));
+    }
+}
+
+fn announce_seed(rng: Res<SimRng>) {
+    info!("Seed: {}", rng.seed);
+}
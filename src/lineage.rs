@@ -0,0 +1,323 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+};
+
+use bevy::{
+    app::AppExit,
+    log::error,
+    prelude::{
+        Added, App, Commands, EventReader, ParallelSystemDescriptorCoercion, Plugin, Query, Res,
+        ResMut,
+    },
+};
+
+use crate::{
+    blob::{Blob, BlobDied, DeathCause},
+    config::LineageConfig,
+    genes::Genes,
+    snapshot::Snapshot,
+    OutDir, SimLabel, Stages, Tick,
+};
+
+pub const CSV_HEADER: &str =
+    "event,tick,id,parents,generation,genome,energy,age,offspring,food_eaten,peak_energy";
+
+struct LineageLog {
+    out: BufWriter<File>,
+    // blobs born up to this tick are already in the file
+    logged_until: Option<u64>,
+}
+
+// Writes every birth and death to <out>/lineage.csv, so the ancestry of any blob
+// can be rebuilt after the run, see phylogeny.rs. Births carry the parent ids
+// (';' separated, none for spawned blobs), the genome and starting energy, deaths
+// how it died and what it got done. A run resumed from a snapshot carries on the
+// log already in <out>, or starts a new one with the restored blobs logged as births
+// at the tick they were born. Whoever is still alive when the run ends gets an
+// alive row
+pub struct LineagePlugin;
+impl Plugin for LineagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(open_lineage).add_system_to_stage(
+            Stages::FoodStage,
+            log_lineage.after(SimLabel::Exit),
+        );
+    }
+}
+
+fn open_lineage(
+    mut commands: Commands,
+    config: Res<LineageConfig>,
+    out: Res<OutDir>,
+    snap: Option<Res<Snapshot>>,
+) {
+    if !config.log {
+        return;
+    }
+    let path = out.0.join("lineage.csv");
+    // the log of the run the snapshot came from, which has every birth up to it
+    let resumed = snap.map(|snap| snap.tick()).filter(|_| path.exists());
+    let file = match resumed {
+        Some(_) => OpenOptions::new().append(true).open(&path),
+        None => File::create(&path),
+    };
+    match file {
+        Ok(file) => {
+            let mut out = BufWriter::new(file);
+            if resumed.is_none() {
+                let _ = writeln!(out, "{}", CSV_HEADER);
+            }
+            commands.insert_resource(LineageLog {
+                out,
+                logged_until: resumed,
+            });
+        }
+        Err(e) => error!("Could not open {}: {}", path.display(), e),
+    }
+}
+
+// One row per event, with every CSV_HEADER field in order and the ones that don't
+// apply left empty
+fn born_row(blob: &Blob, genes: &Genes) -> String {
+    let parents: Vec<String> = blob.parents.iter().map(|p| p.to_string()).collect();
+    format!(
+        "born,{},{},{},{},{},{},,,,",
+        blob.born,
+        blob.id,
+        parents.join(";"),
+        blob.generation,
+        genes,
+        blob.birth_energy
+    )
+}
+
+fn death_row(tick: u64, death: &BlobDied) -> String {
+    let cause = match death.cause {
+        DeathCause::Starved => "starved",
+        DeathCause::Exhausted => "exhausted",
+    };
+    format!(
+        "{},{},{},,{},,,{},{},{},{}",
+        cause,
+        tick,
+        death.id,
+        death.generation,
+        death.age,
+        death.offspring,
+        death.food_eaten,
+        death.peak_energy
+    )
+}
+
+fn alive_row(tick: u64, blob: &Blob) -> String {
+    format!(
+        "alive,{},{},,{},,{},{},{},{},{}",
+        tick,
+        blob.id,
+        blob.generation,
+        blob.energy,
+        blob.age,
+        blob.offspring,
+        blob.food_eaten,
+        blob.peak_energy
+    )
+}
+
+// Runs after everything that spawns or kills blobs this tick
+fn log_lineage(
+    log: Option<ResMut<LineageLog>>,
+    tick: Res<Tick>,
+    born: Query<(&Blob, &Genes), Added<Blob>>,
+    alive: Query<&Blob>,
+    mut deaths: EventReader<BlobDied>,
+    mut exits: EventReader<AppExit>,
+) {
+    let mut log = match log {
+        Some(log) => log,
+        None => return,
+    };
+    let logged = |blob: &Blob| match log.logged_until {
+        Some(tick) => blob.born <= tick,
+        None => false,
+    };
+    // ids, not query order, so seeded runs write the same file
+    let mut born: Vec<_> = born.iter().filter(|(blob, _)| !logged(blob)).collect();
+    born.sort_by_key(|(blob, _)| blob.id);
+    let mut deaths: Vec<_> = deaths.iter().collect();
+    deaths.sort_by_key(|death| death.id);
+
+    let out = &mut log.out;
+    let written = (|| -> io::Result<()> {
+        for (blob, genes) in born {
+            writeln!(out, "{}", born_row(blob, genes))?;
+        }
+        for death in deaths {
+            writeln!(out, "{}", death_row(tick.0, death))?;
+        }
+        if exits.iter().count() > 0 {
+            let mut alive: Vec<&Blob> = alive.iter().collect();
+            alive.sort_by_key(|blob| blob.id);
+            for blob in alive {
+                writeln!(out, "{}", alive_row(tick.0, blob))?;
+            }
+        }
+        out.flush()
+    })();
+    if let Err(e) = written {
+        error!("Could not write lineage: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use bevy::{ecs::event::Events, prelude::App, MinimalPlugins};
+
+    use crate::{
+        blob::{Blob, BlobDied, DeathCause, NextBlobId},
+        genes::{test_genes, Genes},
+        lineage::{alive_row, born_row, death_row, CSV_HEADER},
+        network::Network,
+        phylogeny::Phylogeny,
+        snapshot::{SaveSnapshot, Snapshot},
+        Config, SimulationPlugin,
+    };
+
+    // field name to value, checking there is one value per header field
+    fn fields(row: &str) -> HashMap<&'static str, &str> {
+        let values: Vec<&str> = row.split(',').collect();
+        assert_eq!(values.len(), CSV_HEADER.split(',').count(), "{}", row);
+        CSV_HEADER.split(',').zip(values).collect()
+    }
+
+    #[test]
+    fn rows_match_the_header() {
+        let genes = test_genes();
+        let blob = Blob {
+            energy: 50.,
+            age: 2.5,
+            generation: 3,
+            brain: Box::new(Network::new(&genes)),
+            actions: Default::default(),
+            exhausted: false,
+            offspring: 4,
+            food_eaten: 5,
+            peak_energy: 80.,
+            id: 9,
+            parents: vec![2, 7],
+            born: 60,
+            birth_energy: 25.,
+        };
+
+        let born_text = born_row(&blob, &genes);
+        let born = fields(&born_text);
+        assert_eq!(born["event"], "born");
+        assert_eq!(born["tick"], "60");
+        assert_eq!(born["id"], "9");
+        assert_eq!(born["parents"], "2;7");
+        assert_eq!(born["generation"], "3");
+        assert_eq!(born["genome"].parse::<Genes>(), Ok(genes));
+        // what it was born with, not what it has now
+        assert_eq!(born["energy"], "25");
+        for field in ["age", "offspring", "food_eaten", "peak_energy"] {
+            assert_eq!(born[field], "");
+        }
+
+        let died = BlobDied {
+            cause: DeathCause::Exhausted,
+            id: 9,
+            generation: 3,
+            age: 3.,
+            offspring: 4,
+            food_eaten: 5,
+            peak_energy: 80.,
+        };
+        let death_text = death_row(120, &died);
+        let death = fields(&death_text);
+        assert_eq!(death["event"], "exhausted");
+        assert_eq!(death["tick"], "120");
+        assert_eq!(death["id"], "9");
+        assert_eq!(death["generation"], "3");
+        assert_eq!(death["age"], "3");
+        assert_eq!(death["offspring"], "4");
+        assert_eq!(death["food_eaten"], "5");
+        assert_eq!(death["peak_energy"], "80");
+        for field in ["parents", "genome", "energy"] {
+            assert_eq!(death[field], "");
+        }
+
+        let alive_text = alive_row(90, &blob);
+        let alive = fields(&alive_text);
+        assert_eq!(alive["event"], "alive");
+        assert_eq!(alive["tick"], "90");
+        assert_eq!(alive["energy"], "50");
+        assert_eq!(alive["age"], "2.5");
+        assert_eq!(alive["peak_energy"], "80");
+        assert_eq!(alive["parents"], "");
+        assert_eq!(alive["genome"], "");
+
+        // and phylogeny.rs reads them back
+        let log = format!("{}\n{}\n{}\n", CSV_HEADER, born_text, death_text);
+        let tree = Phylogeny::parse(&log).unwrap();
+        assert_eq!(tree.end, 120);
+    }
+
+    #[test]
+    fn resuming_carries_on_the_log() {
+        let dir = std::env::temp_dir().join(format!("blobs-lineage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let saved = dir.join("saved.ron");
+        let mut config = Config::default();
+        config.lineage.log = true;
+        // the first blobs starve before the snapshot, so there are deaths to keep
+        config.blobs.metabolic_cost = 2.;
+        let plugin = || SimulationPlugin {
+            config: config.clone(),
+            seed: 11,
+            out: dir.clone(),
+        };
+
+        let mut first = App::new();
+        first.add_plugins(MinimalPlugins).add_plugin(plugin());
+        for _ in 0..130 {
+            first.update();
+        }
+        first
+            .world
+            .resource_mut::<Events<SaveSnapshot>>()
+            .send(SaveSnapshot(saved.clone()));
+        first.update();
+        drop(first);
+        let before = fs::read_to_string(dir.join("lineage.csv")).unwrap();
+        assert!(before.contains("\nstarved,"));
+
+        let mut resumed = App::new();
+        resumed
+            .insert_resource(Snapshot::read(&saved).unwrap())
+            .add_plugins(MinimalPlugins)
+            .add_plugin(plugin());
+        for _ in 0..70 {
+            resumed.update();
+        }
+        let next_id = resumed.world.resource::<NextBlobId>().0;
+        drop(resumed);
+        let log = fs::read_to_string(dir.join("lineage.csv")).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        // the first run's rows are kept, with one header, and every blob of both runs
+        // is born exactly once
+        assert!(log.starts_with(&before) && log.len() > before.len());
+        assert_eq!(log.matches("event,").count(), 1);
+        let mut born: Vec<u64> = log
+            .lines()
+            .filter(|line| line.starts_with("born,"))
+            .map(|line| line.split(',').nth(2).unwrap().parse().unwrap())
+            .collect();
+        born.sort_unstable();
+        assert_eq!(born, (0..next_id).collect::<Vec<_>>());
+        assert!(next_id > Config::default().blobs.min_blobs as u64);
+        assert!(Phylogeny::parse(&log).is_ok());
+    }
+}
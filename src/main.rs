@@ -1,142 +1,328 @@
+use std::path::{Path, PathBuf};
+
 use bevy::{
-    math::Vec2,
-    prelude::{
-        App, Commands, Component, OrthographicCameraBundle, Query, Res, StageLabel, SystemStage,
-        Transform,
-    },
-    tasks::ComputeTaskPool,
-    window::{WindowDescriptor, Windows},
-    DefaultPlugins,
+    log::{LogPlugin, LogSettings},
+    prelude::App,
+    window::WindowDescriptor,
+    DefaultPlugins, MinimalPlugins,
+};
+use blobs::{
+    asm::assemble,
+    batch::{self, BatchSummary, RunSpec},
+    blob::SeedGenomes,
+    disasm::disassemble,
+    dot::network_dot,
+    phylogeny::Phylogeny,
+    snapshot::AutoSave,
+    sweep::{Sweep, SweepSummary},
+    Config, Genes, HallOfFame, Network, SimulationPlugin, Snapshot, SpritesPlugin, TickLimit,
 };
 
-mod food;
-use food::FoodPlugin;
+mod cli;
+use clap::Parser;
+use cli::{Args, AsmArgs, BatchArgs, Command, DisasmArgs, DotArgs, PhylogenyArgs, SweepArgs};
 
-mod blob;
-use blob::BlobPlugin;
+fn main() {
+    let args = Args::parse();
+    match args.command {
+        Some(Command::Batch(batch)) => return run_batch(batch),
+        Some(Command::Sweep(sweep)) => return run_sweep(sweep),
+        Some(Command::Phylogeny(phylogeny)) => return run_phylogeny(phylogeny),
+        Some(Command::Disasm(disasm)) => return run_disasm(disasm),
+        Some(Command::Dot(dot)) => return run_dot(dot),
+        Some(Command::Asm(asm)) => return run_asm(asm),
+        None => {}
+    }
 
-mod genes;
-mod network;
+    let snap = args.load.as_ref().map(|path| {
+        Snapshot::read(path).unwrap_or_else(|e| {
+            eprintln!("Could not load snapshot {}: {}", path.display(), e);
+            std::process::exit(1)
+        })
+    });
+    // a restored world keeps the seed it was started with
+    let seed = match (&snap, args.seed) {
+        (Some(snap), _) => snap.seed,
+        (None, Some(seed)) => seed,
+        (None, None) => rand::random(),
+    };
 
-use rand::Rng;
-use rand_distr::StandardNormal;
+    let mut config = load_config(args.config.as_ref());
+    if args.metrics.is_some() {
+        config.metrics.format = args.metrics;
+    }
+    config.lineage.log |= args.lineage;
+    config.species.log |= args.species;
+    create_out(&args.out);
 
-// const HEIGHT: f32 = 480.;
-const HEIGHT: f32 = 720.;
-// const WIDTH: f32 = 640.;
-const WIDTH: f32 = 1280.;
+    let mut app = App::new();
+    app.insert_resource(LogSettings {
+        level: args.log,
+        ..Default::default()
+    });
+    // no window or renderer, the world is the configured size instead of the window size
+    if args.headless {
+        app.add_plugins(MinimalPlugins).add_plugin(LogPlugin);
+    } else {
+        app.insert_resource(WindowDescriptor {
+            title: "Blobs".to_string(),
+            width: config.world.width,
+            height: config.world.height,
+            ..Default::default()
+        })
+        .add_plugins(DefaultPlugins)
+        .add_plugin(SpritesPlugin);
+        if let Some(mode) = args.color {
+            app.insert_resource(mode);
+        }
+    }
+    if let Some(snap) = snap {
+        app.insert_resource(snap);
+    }
+    let mut seeds = Vec::new();
+    if let Some(path) = &args.genomes {
+        seeds.extend(Genes::read_list(path).unwrap_or_else(|e| {
+            eprintln!("Could not load genomes {}: {}", path.display(), e);
+            std::process::exit(1)
+        }));
+    }
+    if let Some(path) = &args.from_hall {
+        let genomes = HallOfFame::read(path).and_then(|hall| hall.genomes());
+        seeds.extend(genomes.unwrap_or_else(|e| {
+            eprintln!("Could not load hall of fame {}: {}", path.display(), e);
+            std::process::exit(1)
+        }));
+    }
+    if !seeds.is_empty() {
+        app.insert_resource(SeedGenomes(seeds));
+    }
+    if let Some(every) = args.snapshot_every {
+        app.insert_resource(AutoSave(every));
+    }
+    if let Some(ticks) = args.ticks {
+        app.insert_resource(TickLimit(ticks));
+    }
+    app.add_plugin(SimulationPlugin {
+        config,
+        seed,
+        out: args.out,
+    })
+    .run();
+}
 
-struct Speed(f32);
-impl Default for Speed {
-    fn default() -> Self {
-        Self(0.3)
+fn load_config(path: Option<&PathBuf>) -> Config {
+    match path {
+        Some(path) => Config::load(path).unwrap_or_else(|e| {
+            eprintln!("Bad config {}: {}", path.display(), e);
+            std::process::exit(1)
+        }),
+        None => Config::default(),
     }
 }
 
-#[derive(Component)]
-struct Chem {
-    // is also colour in shader
-    // id: u8,
-    dissolve_life: u16,
+fn create_out(out: &Path) {
+    if let Err(e) = std::fs::create_dir_all(out) {
+        eprintln!("Could not create {}: {}", out.display(), e);
+        std::process::exit(1)
+    }
 }
 
-// make a grid that will be drawn on by a shader
+// every config with every seed, one headless world each
+fn run_batch(args: BatchArgs) {
+    let base = args.seed.unwrap_or_else(rand::random);
+    let configs: Vec<Option<&PathBuf>> = if args.config.is_empty() {
+        vec![None]
+    } else {
+        args.config.iter().map(Some).collect()
+    };
+    let mut specs = Vec::new();
+    for path in configs {
+        let mut config = load_config(path);
+        if args.metrics.is_some() {
+            config.metrics.format = args.metrics;
+        }
+        let name = batch::config_name(path);
+        for i in 0..args.runs {
+            let seed = base.wrapping_add(i);
+            specs.push(RunSpec {
+                name: batch::run_name(&name, seed),
+                config: config.clone(),
+                seed,
+                ticks: args.ticks,
+                target_generation: None,
+            });
+        }
+    }
+    create_out(&args.out);
 
-struct WinSize {
-    w: f32,
-    h: f32,
-}
+    let jobs = args.jobs.unwrap_or_else(batch::default_jobs);
+    println!(
+        "Running {} worlds for {} ticks on {} threads, first seed {}",
+        specs.len(),
+        args.ticks,
+        jobs,
+        base
+    );
+    let total = specs.len();
+    let runs = batch::run_batch(&specs, jobs, &args.out, |done, run| {
+        println!(
+            "[{}/{}] {}: {} blobs, max generation {}",
+            done, total, run.name, run.final_blobs, run.max_generation
+        )
+    });
 
-#[derive(Component)]
-struct Velocity(Vec2);
-impl Default for Velocity {
-    fn default() -> Self {
-        Self(Vec2::ZERO)
+    let summary = BatchSummary::new(runs);
+    if let Err(e) = summary.write(&args.out) {
+        eprintln!("Could not write summary to {}: {}", args.out.display(), e);
+        std::process::exit(1)
+    }
+    println!(
+        "Final blobs: min {} mean {:.1} max {}",
+        summary.final_blobs.min, summary.final_blobs.mean, summary.final_blobs.max
+    );
+    println!(
+        "Max generation: min {} mean {:.1} max {}",
+        summary.max_generation.min, summary.max_generation.mean, summary.max_generation.max
+    );
+    if let Some(best) = summary.runs.iter().max_by_key(|r| r.max_generation) {
+        println!(
+            "Highest generation {} in {}: {}",
+            best.max_generation, best.name, best.max_generation_genome
+        );
     }
 }
 
-#[derive(Component)]
-struct Acceleration(Vec2);
-impl Default for Acceleration {
-    fn default() -> Self {
-        Self(Vec2::ZERO)
+fn run_sweep(args: SweepArgs) {
+    let sweep = Sweep::load(&args.sweep).unwrap_or_else(|e| {
+        eprintln!("Bad sweep {}: {}", args.sweep.display(), e);
+        std::process::exit(1)
+    });
+    let mut base = load_config(args.config.as_ref());
+    if args.metrics.is_some() {
+        base.metrics.format = args.metrics;
+    }
+    let points = sweep.points(&base).unwrap_or_else(|e| {
+        eprintln!("Bad sweep {}: {}", args.sweep.display(), e);
+        std::process::exit(1)
+    });
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let specs = sweep.specs(&points, seed);
+    create_out(&args.out);
+
+    let jobs = args.jobs.unwrap_or_else(batch::default_jobs);
+    println!(
+        "Sweeping {} points x {} replicates for {} ticks on {} threads, first seed {}",
+        points.len(),
+        sweep.replicates,
+        sweep.ticks,
+        jobs,
+        seed
+    );
+    let total = specs.len();
+    let runs = batch::run_batch(&specs, jobs, &args.out, |done, run| {
+        println!(
+            "[{}/{}] {}: {} blobs, max generation {}",
+            done, total, run.name, run.final_blobs, run.max_generation
+        )
+    });
+
+    let summary = SweepSummary::new(&sweep, &points, &runs);
+    let written = summary
+        .write(&args.out)
+        .and_then(|_| BatchSummary::new(runs).write(&args.out));
+    if let Err(e) = written {
+        eprintln!("Could not write summary to {}: {}", args.out.display(), e);
+        std::process::exit(1)
     }
+    println!("{}", summary.table());
 }
 
-// TODO:
-// - make rng simwide, instead of creating new one when needed
-// - make entire 'screen' follow fluid dynamics to move everything
-// - turn spawn command into a closure, most of code is repeated. Maybe a macro
+fn run_phylogeny(args: PhylogenyArgs) {
+    let mut tree = Phylogeny::read(&args.lineage).unwrap_or_else(|e| {
+        eprintln!("Bad lineage log {}: {}", args.lineage.display(), e);
+        std::process::exit(1)
+    });
+    if args.pruned {
+        tree = tree.pruned();
+    }
+    create_out(&args.out);
+    if let Err(e) = tree.write(&args.out) {
+        eprintln!("Could not write phylogeny to {}: {}", args.out.display(), e);
+        std::process::exit(1)
+    }
+    let alive = tree.nodes.values().filter(|n| n.died.is_none()).count();
+    println!(
+        "{} blobs from {} founders, {} alive at tick {}",
+        tree.nodes.len(),
+        tree.roots.len(),
+        alive,
+        tree.end
+    );
+}
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
-pub enum Stages {
-    FoodStage,
-    BlobStage,
+// genomes given on the command line, then from a file
+fn read_genomes(texts: &[String], file: Option<&PathBuf>) -> Vec<Genes> {
+    let mut genomes = Vec::new();
+    for text in texts {
+        genomes.push(text.parse::<Genes>().unwrap_or_else(|e| {
+            eprintln!("Bad genome {}: {}", text, e);
+            std::process::exit(1)
+        }));
+    }
+    if let Some(path) = file {
+        genomes.extend(Genes::read_list(path).unwrap_or_else(|e| {
+            eprintln!("Could not load genomes {}: {}", path.display(), e);
+            std::process::exit(1)
+        }));
+    }
+    genomes
 }
 
-fn main() {
-    App::new()
-        .insert_resource(WindowDescriptor {
-            title: "Blobs".to_string(),
-            width: WIDTH,
-            height: HEIGHT,
-            ..Default::default()
-        })
-        .add_plugins(DefaultPlugins)
-        .add_stage(Stages::BlobStage, SystemStage::parallel())
-        .add_stage_after(
-            Stages::BlobStage,
-            Stages::FoodStage,
-            SystemStage::parallel(),
-        )
-        .add_plugin(FoodPlugin)
-        .add_plugin(BlobPlugin)
-        .add_startup_system(setup)
-        .add_system(brownian_drift)
-        .run();
+fn run_disasm(args: DisasmArgs) {
+    let genomes = read_genomes(&args.genomes, args.file.as_ref());
+    let listings: Vec<String> = genomes.iter().map(|g| disassemble(g).to_string()).collect();
+    print!("{}", listings.join("\n"));
 }
 
-fn setup(mut commands: Commands, win: Res<Windows>) {
-    let w = win.get_primary().unwrap();
-
-    // set origin to top left
-    let mut camera = OrthographicCameraBundle::new_2d();
-    camera.transform.translation.x += w.width() / 2.;
-    camera.transform.translation.y += w.height() / 2.;
-    commands.spawn_bundle(camera);
-    commands.insert_resource(WinSize {
-        w: w.width() - 5.,
-        h: w.height() - 5.,
-    });
+// one genome per file, in a form --genomes reads
+fn run_asm(args: AsmArgs) {
+    for path in &args.files {
+        let genes = std::fs::read_to_string(path)
+            .map_err(|e| e.into())
+            .and_then(|text| assemble(&text).map_err(|e| e.into()))
+            .unwrap_or_else(|e: Box<dyn std::error::Error>| {
+                eprintln!("Could not assemble {}: {}", path.display(), e);
+                std::process::exit(1)
+            });
+        println!("{}", genes);
+    }
 }
 
-// Moves all entities according to Brownian motion to simulate the movement of water
-// Also takes other movement into account
-const BROWN_SCALE: f32 = 8.;
-
-fn brownian_drift(
-    win: Res<WinSize>,
-    mut query: Query<(&mut Acceleration, &mut Velocity, &mut Transform)>,
-    pool: Res<ComputeTaskPool>,
-) {
-    query.par_for_each_mut(&pool, 128, |(mut accel, mut vel, mut trans)| {
-        // Brownian
-        let mut r = rand::thread_rng();
-        // maybe try perlin noise?
-        accel.0.x += r.sample::<f32, _>(StandardNormal) / BROWN_SCALE;
-        accel.0.y += r.sample::<f32, _>(StandardNormal) / BROWN_SCALE;
-
-        vel.0.x += accel.0.x;
-        vel.0.y += accel.0.y;
-
-        trans.translation.x = ((trans.translation.x + vel.0.x) % win.w + win.w) % win.w;
-        trans.translation.y = ((trans.translation.y + vel.0.y) % win.h + win.h) % win.h;
-
-        // drag
-        vel.0.x *= 0.8;
-        vel.0.y *= 0.8;
-
-        accel.0.x = 0.;
-        accel.0.y = 0.;
-    });
+fn run_dot(args: DotArgs) {
+    let mut genomes = read_genomes(&args.genomes, args.file.as_ref());
+    if let Some(path) = &args.hall {
+        let hall = HallOfFame::read(path).and_then(|hall| hall.genomes());
+        genomes.extend(hall.unwrap_or_else(|e| {
+            eprintln!("Could not load hall of fame {}: {}", path.display(), e);
+            std::process::exit(1)
+        }));
+    }
+    if let Some(out) = &args.out {
+        create_out(out);
+    }
+    for (i, genes) in genomes.iter().enumerate() {
+        let name = format!("brain-{}", i);
+        let dot = network_dot(&Network::new(genes), &name, Some(&genes.to_string()));
+        match &args.out {
+            Some(out) => {
+                let path = out.join(format!("{}.dot", name));
+                if let Err(e) = std::fs::write(&path, dot) {
+                    eprintln!("Could not write {}: {}", path.display(), e);
+                    std::process::exit(1)
+                }
+                println!("{} {}", path.display(), genes);
+            }
+            None => print!("{}", dot),
+        }
+    }
 }
@@ -0,0 +1,339 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    str::FromStr,
+};
+
+use bevy::{
+    log::error,
+    prelude::{
+        App, Commands, CoreStage, EventReader, ParallelSystemDescriptorCoercion, Plugin, Query,
+        Res, ResMut, SystemLabel, With,
+    },
+    utils::HashSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blob::{Blob, BlobBorn, BlobDied, CurBlobs, DeathCause},
+    config::MetricsConfig,
+    food::{CurFood, FoodEaten},
+    genes::Genes,
+    Chem, OutDir, SimLabel, Tick,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetricsFormat {
+    Csv,
+    // one JSON object per line
+    Json,
+}
+impl MetricsFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            MetricsFormat::Csv => "csv",
+            MetricsFormat::Json => "jsonl",
+        }
+    }
+}
+impl FromStr for MetricsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(MetricsFormat::Csv),
+            "json" | "jsonl" => Ok(MetricsFormat::Json),
+            _ => Err(format!(
+                "unknown metrics format {}, expected csv or json",
+                s
+            )),
+        }
+    }
+}
+
+// Everything that happened since the last sample
+#[derive(Default)]
+struct Tally {
+    births: u64,
+    spawned: u64,
+    starved: u64,
+    exhausted: u64,
+    food_eaten: u64,
+}
+
+// One row of the time series
+#[derive(Serialize)]
+pub struct Sample {
+    pub tick: u64,
+    pub blobs: u32,
+    pub food: u32,
+    pub chems: u32,
+    pub energy_mean: f32,
+    pub energy_min: f32,
+    pub energy_max: f32,
+    pub age_mean: f32,
+    pub age_min: f32,
+    pub age_max: f32,
+    pub gen_min: u16,
+    pub gen_median: u16,
+    pub gen_max: u16,
+    pub gen_mean: f32,
+    pub births: u64,
+    pub spawned: u64,
+    pub deaths_starved: u64,
+    pub deaths_exhausted: u64,
+    pub food_eaten: u64,
+    // number of different genomes alive
+    pub genomes: u32,
+    // mean entropy of each genome bit across the population, 0 is a clonal population
+    pub diversity: f32,
+    // mean of the mutation rate multipliers carried in the genomes
+    pub mut_scale_mean: f32,
+}
+impl Sample {
+    const CSV_HEADER: &'static str = "tick,blobs,food,chems,\
+        energy_mean,energy_min,energy_max,age_mean,age_min,age_max,\
+        gen_min,gen_median,gen_max,gen_mean,\
+        births,spawned,deaths_starved,deaths_exhausted,food_eaten,genomes,diversity,mut_scale_mean";
+
+    fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.tick,
+            self.blobs,
+            self.food,
+            self.chems,
+            self.energy_mean,
+            self.energy_min,
+            self.energy_max,
+            self.age_mean,
+            self.age_min,
+            self.age_max,
+            self.gen_min,
+            self.gen_median,
+            self.gen_max,
+            self.gen_mean,
+            self.births,
+            self.spawned,
+            self.deaths_starved,
+            self.deaths_exhausted,
+            self.food_eaten,
+            self.genomes,
+            self.diversity,
+            self.mut_scale_mean,
+        )
+    }
+}
+
+// mean over every genome bit position of the entropy of that bit in the population,
+// positions past the end of shorter genomes only count the genomes that have them
+pub fn genome_diversity(genes: &[&Genes]) -> f32 {
+    let words: Vec<Vec<u16>> = genes.iter().map(|g| g.words().collect()).collect();
+    let len = words.iter().map(|w| w.len()).max().unwrap_or(0);
+    if len == 0 {
+        return 0.;
+    }
+    let total: f32 = (0..len * 16)
+        .map(|bit| {
+            let (word, mask) = (bit / 16, 1u16 << (bit % 16));
+            let have: Vec<u16> = words.iter().filter_map(|w| w.get(word).copied()).collect();
+            let ones = have.iter().filter(|w| *w & mask != 0).count() as f32;
+            let p = ones / have.len() as f32;
+            if p == 0. || p == 1. {
+                0.
+            } else {
+                -(p * p.log2() + (1. - p) * (1. - p).log2())
+            }
+        })
+        .sum();
+    total / (len * 16) as f32
+}
+
+// (min, mean, max), all zero for an empty population
+fn spread(values: impl Iterator<Item = f32>) -> (f32, f32, f32) {
+    let (mut min, mut max, mut sum, mut n) = (f32::MAX, f32::MIN, 0., 0);
+    for v in values {
+        min = min.min(v);
+        max = max.max(v);
+        sum += v;
+        n += 1;
+    }
+    if n == 0 {
+        (0., 0., 0.)
+    } else {
+        (min, sum / n as f32, max)
+    }
+}
+
+struct MetricsLog {
+    format: MetricsFormat,
+    out: BufWriter<File>,
+}
+
+// Samples the population every `interval` ticks into <out>/metrics.csv or .jsonl
+pub struct MetricsPlugin;
+impl Plugin for MetricsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Tally::default())
+            .add_startup_system(open_metrics)
+            .add_system_to_stage(
+                CoreStage::First,
+                tally.label(MetricsLabel).before(SimLabel::Tick),
+            )
+            .add_system_to_stage(
+                CoreStage::First,
+                sample.after(MetricsLabel).before(SimLabel::Tick),
+            );
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct MetricsLabel;
+
+fn open_metrics(mut commands: Commands, config: Res<MetricsConfig>, out: Res<OutDir>) {
+    let format = match config.format {
+        Some(format) => format,
+        None => return,
+    };
+    let path = out.0.join(format!("metrics.{}", format.extension()));
+    match File::create(&path) {
+        Ok(file) => {
+            let mut out = BufWriter::new(file);
+            if format == MetricsFormat::Csv {
+                let _ = writeln!(out, "{}", Sample::CSV_HEADER);
+            }
+            commands.insert_resource(MetricsLog { format, out });
+        }
+        Err(e) => error!("Could not create {}: {}", path.display(), e),
+    }
+}
+
+fn tally(
+    mut tally: ResMut<Tally>,
+    mut births: EventReader<BlobBorn>,
+    mut deaths: EventReader<BlobDied>,
+    mut eaten: EventReader<FoodEaten>,
+) {
+    for birth in births.iter() {
+        if birth.random {
+            tally.spawned += 1;
+        } else {
+            tally.births += 1;
+        }
+    }
+    for death in deaths.iter() {
+        match death.cause {
+            DeathCause::Starved => tally.starved += 1,
+            DeathCause::Exhausted => tally.exhausted += 1,
+        }
+    }
+    for food in eaten.iter() {
+        tally.food_eaten += food.0 as u64;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sample(
+    log: Option<ResMut<MetricsLog>>,
+    config: Res<MetricsConfig>,
+    tick: Res<Tick>,
+    mut tally: ResMut<Tally>,
+    cur_blobs: Res<CurBlobs>,
+    cur_food: Res<CurFood>,
+    blob_query: Query<(&Blob, &Genes)>,
+    chem_query: Query<(), With<Chem>>,
+) {
+    let mut log = match log {
+        Some(log) => log,
+        None => return,
+    };
+    if tick.0 == 0 || tick.0 % config.interval != 0 {
+        return;
+    }
+
+    let (energy_min, energy_mean, energy_max) = spread(blob_query.iter().map(|(b, _)| b.energy));
+    let (age_min, age_mean, age_max) = spread(blob_query.iter().map(|(b, _)| b.age));
+    let mut gens: Vec<u16> = blob_query.iter().map(|(b, _)| b.generation).collect();
+    gens.sort_unstable();
+    let genes: Vec<&Genes> = blob_query.iter().map(|(_, g)| g).collect();
+    let distinct: HashSet<&Genes> = genes.iter().copied().collect();
+
+    let sample = Sample {
+        tick: tick.0,
+        blobs: cur_blobs.0,
+        food: cur_food.0,
+        chems: chem_query.iter().count() as u32,
+        energy_mean,
+        energy_min,
+        energy_max,
+        age_mean,
+        age_min,
+        age_max,
+        gen_min: gens.first().copied().unwrap_or(0),
+        gen_median: gens.get(gens.len() / 2).copied().unwrap_or(0),
+        gen_max: gens.last().copied().unwrap_or(0),
+        gen_mean: spread(gens.iter().map(|g| *g as f32)).1,
+        births: tally.births,
+        spawned: tally.spawned,
+        deaths_starved: tally.starved,
+        deaths_exhausted: tally.exhausted,
+        food_eaten: tally.food_eaten,
+        genomes: distinct.len() as u32,
+        diversity: genome_diversity(&genes),
+        mut_scale_mean: spread(genes.iter().map(|g| g.mut_scale() as f32)).1,
+    };
+    *tally = Tally::default();
+
+    let log = &mut *log;
+    let written = match log.format {
+        MetricsFormat::Csv => writeln!(log.out, "{}", sample.csv_row()),
+        MetricsFormat::Json => serde_json::to_writer(&mut log.out, &sample)
+            .map_err(|e| e.into())
+            .and_then(|_| writeln!(log.out)),
+    };
+    // flushing every sample keeps the file useful if the run is killed
+    if let Err(e) = written.and_then(|_| log.out.flush()) {
+        error!("Could not write metrics: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        genes::Genes,
+        metrics::{genome_diversity, spread},
+    };
+
+    fn genes(words: &[u16]) -> Genes {
+        Genes {
+            header: words[0],
+            synapses: words[1..].to_vec(),
+        }
+    }
+
+    #[test]
+    fn diversity_is_mean_bit_entropy() {
+        assert_eq!(genome_diversity(&[]), 0.);
+
+        let a = genes(&[0xe000, 0x396b, 0x9280]);
+        assert_eq!(genome_diversity(&[&a, &a, &a]), 0.);
+
+        // one bit split half and half is 1 of the 48
+        let b = genes(&[0xe000, 0x396b, 0x9281]);
+        assert_eq!(genome_diversity(&[&a, &b]), 1. / 48.);
+
+        // the second word only counts the genomes that have one: a clone with an extra
+        // word adds nothing, two that disagree on all of it add 16 bits of 32
+        let short = genes(&[0xe000]);
+        let ones = genes(&[0xe000, 0xffff]);
+        let zeros = genes(&[0xe000, 0x0000]);
+        assert_eq!(genome_diversity(&[&short, &ones]), 0.);
+        assert_eq!(genome_diversity(&[&short, &ones, &zeros]), 0.5);
+    }
+
+    #[test]
+    fn spread_of_nothing_is_zero() {
+        assert_eq!(spread([].into_iter()), (0., 0., 0.));
+        assert_eq!(spread([1., 6., 2.].into_iter()), (1., 3., 6.));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::genes::{Genes, ParseGenesError};
+
+// Genome layouts so far, every text genome is tagged with the one it was written in:
+// 0: a bare decimal u128, header in the top 16 bits then 7 synapses, the 12 low header
+//    bits unused
+// 1: header and any number of synapses as hex words, the low 8 header bits are the
+//    lineage's mutation rate. The from index overlapped the from_internal bit and the
+//    to index the to_internal bit, so only internal 2 and 3 fed anything and only
+//    internal 4 to 7 were fed
+// 2: synapse bits as Network::new describes them, every index has its own 3 bits
+// When Network::new starts decoding genes differently, bump CURRENT, teach `decode`
+// the old text and add a migrator that rewrites an old genome into the new layout,
+// keeping what it did as far as possible
+pub const CURRENT: u32 = 2;
+
+// MIGRATORS[v] turns a version v genome into a version v + 1 one
+const MIGRATORS: [fn(Genes) -> Genes; CURRENT as usize] = [v0_to_v1, v1_to_v2];
+
+// reads the part after the tag in the layout of `version`
+fn decode(version: u32, body: &str) -> Result<Genes, ParseGenesError> {
+    match version {
+        0 => body
+            .parse::<u128>()
+            .map(Genes::from_u128)
+            .map_err(|_| ParseGenesError::BadGene(body.to_string())),
+        1 | 2 => Genes::parse_words(body),
+        v => Err(ParseGenesError::Version(v)),
+    }
+}
+
+// Reads a genome written in any known version and brings it up to CURRENT
+pub fn from_version(version: u32, body: &str) -> Result<Genes, ParseGenesError> {
+    let mut genes = decode(version, body)?;
+    for migrate in &MIGRATORS[version as usize..] {
+        genes = migrate(genes);
+    }
+    Ok(genes)
+}
+
+// the unused bits were random, so the lineage starts at the configured mutation rate
+fn v0_to_v1(mut genes: Genes) -> Genes {
+    genes.header = (genes.header & 0xf000) | 0x80;
+    genes
+}
+
+// Every synapse decoded the old way fits the new layout, so each one is moved over
+// as it is and the networks come out the same
+fn v1_to_v2(mut genes: Genes) -> Genes {
+    for gene in &mut genes.synapses {
+        // bits 14-15 were the from index and 9-11 the to index
+        let from = *gene >> 14;
+        let to = (*gene >> 9) & 7;
+        *gene = (*gene & 0x8000) | from << 12 | (*gene & 0x0800) | to << 8 | (*gene & 0x00ff);
+    }
+    genes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        genes::{Genes, ParseGenesError},
+        migrate::v1_to_v2,
+        network::{DecodedSynapse, Node},
+    };
+
+    #[test]
+    fn legacy_genomes_still_load() {
+        // the old success collection entry
+        let old: Genes = "27412239664388069923010120978984735311".parse().unwrap();
+        assert_eq!(
+            old,
+            "blob0:27412239664388069923010120978984735311"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            old.to_string(),
+            "blob2:1080.13c3.00ee.beea.0d40.01a7.03d6.014f"
+        );
+        assert_eq!(old.mut_scale(), 1.);
+        assert_eq!(
+            "blob3:1080".parse::<Genes>(),
+            Err(ParseGenesError::Version(3))
+        );
+    }
+
+    #[test]
+    fn v1_synapses_keep_their_neurons() {
+        // 4 internal, internal:2 -> output:move-y w=1 and input:chem-x -> internal:5
+        let old: Genes = "blob1:4080.8220.0aff".parse().unwrap();
+        assert_eq!(old.to_string(), "blob2:4080.a120.0dff");
+
+        for gene in 0..=u16::MAX {
+            // the v1 decoding
+            let from = (gene >> 14) as u8;
+            let to = (gene >> 9) as u8 & 7;
+            let before = DecodedSynapse {
+                from: if gene & 0x8000 != 0 {
+                    Node::Internal(from)
+                } else {
+                    Node::Input(from)
+                },
+                to: if gene & 0x0800 != 0 {
+                    Node::Internal(to)
+                } else {
+                    Node::Output(to)
+                },
+                weight: gene as u8 as i8 as f32 / 32.,
+            };
+            let moved = v1_to_v2(Genes {
+                header: 0,
+                synapses: vec![gene],
+            });
+            assert_eq!(DecodedSynapse::new(moved.synapses[0]), before);
+        }
+    }
+}
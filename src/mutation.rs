@@ -0,0 +1,214 @@
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+use crate::{
+    config::{invalid, non_negative, ConfigError, GeneConfig},
+    genes::Genes,
+};
+
+// The ways a genome can change when copied, each with its own rate in GeneConfig.
+// Replication applies all of them in the order of ALL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    // per gene, header included: flip one random bit
+    BitFlip,
+    // per synapse gene: add gaussian noise to the 8 bit weight
+    WeightNudge,
+    // per synapse gene: randomise either the source or the target bits
+    Rewire,
+    // per genome: one more or one less internal node in the header
+    InternalCount,
+    // per genome: copy a synapse gene next to itself, up to max_synapses
+    Duplicate,
+    // per genome: drop a synapse gene
+    Delete,
+}
+
+impl Mutation {
+    pub const ALL: [Mutation; 6] = [
+        Mutation::BitFlip,
+        Mutation::WeightNudge,
+        Mutation::Rewire,
+        Mutation::InternalCount,
+        Mutation::Duplicate,
+        Mutation::Delete,
+    ];
+
+    pub fn rate(self, config: &GeneConfig) -> f64 {
+        match self {
+            Mutation::BitFlip => config.mut_rate,
+            Mutation::WeightNudge => config.weight_rate,
+            Mutation::Rewire => config.rewire_rate,
+            Mutation::InternalCount => config.internal_rate,
+            Mutation::Duplicate => config.dup_rate,
+            Mutation::Delete => config.del_rate,
+        }
+    }
+
+    // `scale` multiplies the configured rate, see Genes::mut_scale
+    pub fn apply(
+        self,
+        genes: &mut Genes,
+        r: &mut impl Rng,
+        config: &GeneConfig,
+        scale: f64,
+    ) -> Result<(), ConfigError> {
+        let rate = (self.rate(config) * scale).min(1.);
+        if rate == 0. {
+            return Ok(());
+        }
+        match self {
+            Mutation::BitFlip => {
+                if r.gen_bool(rate) {
+                    genes.header ^= 1 << r.gen_range(0..16);
+                }
+                for gene in &mut genes.synapses {
+                    if r.gen_bool(rate) {
+                        *gene ^= 1 << r.gen_range(0..16);
+                    }
+                }
+            }
+            Mutation::WeightNudge => {
+                // weights are stored as 32nds
+                non_negative("genes.weight_sigma", config.weight_sigma)?;
+                let noise = Normal::new(0., config.weight_sigma * 32.)
+                    .map_err(|_| invalid("genes.weight_sigma", "is too large"))?;
+                for gene in &mut genes.synapses {
+                    if r.gen_bool(rate) {
+                        let weight = *gene as u8 as i8 as f32 + noise.sample(r);
+                        let weight = weight.round().clamp(i8::MIN as f32, i8::MAX as f32);
+                        *gene = (*gene & 0xff00) | (weight as i8 as u8 as u16);
+                    }
+                }
+            }
+            Mutation::Rewire => {
+                for gene in &mut genes.synapses {
+                    if r.gen_bool(rate) {
+                        // top 4 bits are where it comes from, the next 4 where it goes
+                        let shift = if r.gen_bool(0.5) { 12 } else { 8 };
+                        let bits: u16 = r.gen_range(0..16);
+                        *gene = (*gene & !(0xf << shift)) | (bits << shift);
+                    }
+                }
+            }
+            Mutation::InternalCount => {
+                if r.gen_bool(rate) {
+                    let n = genes.header >> 12;
+                    let n = if n == 0 || (n < 15 && r.gen_bool(0.5)) {
+                        n + 1
+                    } else {
+                        n - 1
+                    };
+                    genes.header = (genes.header & 0x0fff) | (n << 12);
+                }
+            }
+            Mutation::Duplicate => {
+                if !genes.synapses.is_empty()
+                    && genes.synapses.len() < config.max_synapses as usize
+                    && r.gen_bool(rate)
+                {
+                    let i = r.gen_range(0..genes.synapses.len());
+                    genes.synapses.insert(i, genes.synapses[i]);
+                }
+            }
+            Mutation::Delete => {
+                if !genes.synapses.is_empty() && r.gen_bool(rate) {
+                    let i = r.gen_range(0..genes.synapses.len());
+                    genes.synapses.remove(i);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::{config::GeneConfig, genes::test_genes, mutation::Mutation};
+
+    // only the given operator, always firing
+    fn only(op: Mutation) -> GeneConfig {
+        let mut config = GeneConfig {
+            mut_rate: 0.,
+            dup_rate: 0.,
+            del_rate: 0.,
+            weight_rate: 0.,
+            rewire_rate: 0.,
+            internal_rate: 0.,
+            ..Default::default()
+        };
+        match op {
+            Mutation::BitFlip => config.mut_rate = 1.,
+            Mutation::WeightNudge => config.weight_rate = 1.,
+            Mutation::Rewire => config.rewire_rate = 1.,
+            Mutation::InternalCount => config.internal_rate = 1.,
+            Mutation::Duplicate => config.dup_rate = 1.,
+            Mutation::Delete => config.del_rate = 1.,
+        }
+        config
+    }
+
+    #[test]
+    fn operators_touch_their_own_bits() {
+        let mut r = ChaCha8Rng::seed_from_u64(0);
+        let genes = test_genes();
+        for _ in 0..50 {
+            let child = genes
+                .replicate(&mut r, &only(Mutation::WeightNudge))
+                .unwrap();
+            assert_eq!(child.header, genes.header);
+            for (a, b) in child.synapses.iter().zip(&genes.synapses) {
+                assert_eq!(a & 0xff00, b & 0xff00);
+            }
+
+            let child = genes.replicate(&mut r, &only(Mutation::Rewire)).unwrap();
+            for (a, b) in child.synapses.iter().zip(&genes.synapses) {
+                assert_eq!(a & 0x00ff, b & 0x00ff);
+            }
+
+            let child = genes
+                .replicate(&mut r, &only(Mutation::InternalCount))
+                .unwrap();
+            let (n, m) = (genes.header >> 12, child.header >> 12);
+            assert!(n + 1 == m || n == m + 1);
+            assert_eq!(child.synapses, genes.synapses);
+        }
+    }
+
+    #[test]
+    fn evolved_rate_scales_operators() {
+        let mut r = ChaCha8Rng::seed_from_u64(0);
+        let mut genes = test_genes();
+        let config = GeneConfig {
+            evolve_rate: true,
+            ..only(Mutation::Duplicate)
+        };
+        // lowest scale, 1/16 of the configured rate
+        genes.header &= 0xff00;
+        assert_eq!(genes.mut_scale(), 1. / 16.);
+        let grown = (0..1000)
+            .filter(|_| genes.replicate(&mut r, &config).unwrap().synapses.len() > 7)
+            .count();
+        assert!(grown > 20 && grown < 120, "{}", grown);
+    }
+
+    #[test]
+    fn bad_sigma_is_an_error() {
+        let mut r = ChaCha8Rng::seed_from_u64(0);
+        let genes = test_genes();
+        for sigma in [-1., f32::NAN] {
+            let config = GeneConfig {
+                weight_sigma: sigma,
+                ..only(Mutation::WeightNudge)
+            };
+            assert!(genes.replicate(&mut r, &config).is_err());
+            let mut child = genes.clone();
+            assert!(Mutation::WeightNudge
+                .apply(&mut child, &mut r, &config, 1.)
+                .is_err());
+        }
+    }
+}
@@ -1,6 +1,10 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
 use crate::genes::Genes;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Neuron {
     pub weight: f32,
     pub cur_sum: f32,
@@ -11,21 +15,163 @@ impl Neuron {
     }
 }
 
-const FOUR_BITS: u128 = 15u128;
-const SXTEEN_BITS: u128 = 65535u128;
-// const N_SYNAPS: usize = 7;
 const N_INPUT: u8 = 4;
 const N_OUTPUT: u8 = 4;
 
-#[derive(Debug)]
+// what the blob systems put in and take out, by index
+pub const INPUT_NAMES: [&str; N_INPUT as usize] = ["chem-x", "chem-y", "energy", "oscillator"];
+pub const OUTPUT_NAMES: [&str; N_OUTPUT as usize] = ["move-x", "move-y", "consume", "reproduce"];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct Synapse {
     from: u8,
     to: u8,
     weight: f32,
 }
 
+// A neuron a synapse gene points at, whether or not the network has it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Node {
+    Input(u8),
+    Internal(u8),
+    Output(u8),
+}
+impl Node {
+    pub fn exists(&self, n_internal: usize) -> bool {
+        match *self {
+            Node::Input(i) => i < N_INPUT,
+            Node::Internal(i) => (i as usize) < n_internal,
+            Node::Output(i) => i < N_OUTPUT,
+        }
+    }
+}
+// input:energy, internal:3, output:move-x
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let named = |names: &[&str], i: u8| match names.get(i as usize) {
+            Some(name) => name.to_string(),
+            None => i.to_string(),
+        };
+        match self {
+            Node::Input(i) => write!(f, "input:{}", named(&INPUT_NAMES, *i)),
+            Node::Internal(i) => write!(f, "internal:{}", i),
+            Node::Output(i) => write!(f, "output:{}", named(&OUTPUT_NAMES, *i)),
+        }
+    }
+}
+
+// The other way round, indices work in place of names
+impl FromStr for Node {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || {
+            format!(
+                "{} is not a neuron, expected input:<name>, internal:<index> or output:<name>",
+                s
+            )
+        };
+        let (layer, which) = s.split_once(':').ok_or_else(bad)?;
+        let index = |names: &[&str]| -> Result<u8, String> {
+            match names.iter().position(|name| *name == which) {
+                Some(i) => Ok(i as u8),
+                None => which.parse().map_err(|_| bad()),
+            }
+        };
+        match layer {
+            "input" => Ok(Node::Input(index(&INPUT_NAMES)?)),
+            "internal" => Ok(Node::Internal(index(&[])?)),
+            "output" => Ok(Node::Output(index(&OUTPUT_NAMES)?)),
+            _ => Err(bad()),
+        }
+    }
+}
+
+// Which bundle of the network a synapse gene goes in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SynapseKind {
+    Direct,
+    IntOut,
+    ToInt,
+    SelfLoop,
+    Int,
+}
+
+// One synapse gene taken apart, see Network::new for the layout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodedSynapse {
+    pub from: Node,
+    pub to: Node,
+    pub weight: f32,
+}
+impl DecodedSynapse {
+    pub fn new(mask: u16) -> DecodedSynapse {
+        // get first bit
+        let from_internal = mask >= 1u16 << 15;
+        // get bits 2-4
+        let from = ((mask & (7u16 << 12)) >> 12) as u8;
+        // get 5th bit
+        let to_internal = mask & (1u16 << 11) != 0u16;
+        // get bits 6-8
+        let to = ((mask & (7u16 << 8)) >> 8) as u8;
+
+        // get last ten bits
+        // normalized to [-4.0..4.0]
+        // TODO: consider cubing for accuracy
+        let weight = (mask & 255u16) as i8 as f32 / 32.;
+
+        DecodedSynapse {
+            from: if from_internal {
+                Node::Internal(from)
+            } else {
+                Node::Input(from)
+            },
+            to: if to_internal {
+                Node::Internal(to)
+            } else {
+                Node::Output(to)
+            },
+            weight,
+        }
+    }
+
+    // None when it points at a neuron a network with n_internal nodes doesn't have
+    pub fn kind(&self, n_internal: usize) -> Option<SynapseKind> {
+        let n_internal = n_internal as u8;
+        match (self.from, self.to) {
+            (Node::Internal(from), Node::Internal(to)) if from == to => {
+                (from < n_internal).then_some(SynapseKind::SelfLoop)
+            }
+            (Node::Internal(from), Node::Internal(to)) => {
+                (from < n_internal && to < n_internal).then_some(SynapseKind::Int)
+            }
+            (Node::Input(from), Node::Internal(to)) => {
+                (from < N_INPUT && to < n_internal).then_some(SynapseKind::ToInt)
+            }
+            (Node::Internal(from), Node::Output(to)) => {
+                (from < n_internal && to < N_OUTPUT).then_some(SynapseKind::IntOut)
+            }
+            (Node::Input(from), Node::Output(to)) => {
+                (from < N_INPUT && to < N_OUTPUT).then_some(SynapseKind::Direct)
+            }
+            _ => None,
+        }
+    }
+
+    fn synapse(&self) -> Synapse {
+        let index = |node| match node {
+            Node::Input(i) | Node::Internal(i) | Node::Output(i) => i,
+        };
+        Synapse {
+            from: index(self.from),
+            to: index(self.to),
+            weight: self.weight,
+        }
+    }
+}
+
 // TODO: pack these into one vec and set slice idx for faster compute
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct SynBundle {
     // input to output
     direct_synaps: Vec<Synapse>,
@@ -40,43 +186,27 @@ struct SynBundle {
 }
 
 // TODO: make more internal nodes use more energy
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Network {
     pub inputs: Vec<Neuron>,
     internal: Vec<Neuron>,
     pub outputs: Vec<Neuron>,
     s_bundle: SynBundle,
+    // None: internal neurons start every tick from nothing. Some(leak): what they
+    // summed carries over to the next tick, less this fraction of it
+    leak: Option<f32>,
 }
 impl Network {
     // gene contains num internal nodes, weights of inputs, and details about connections (where and weight)
-    // one u128 is a gene
-    // first 4 is num internal nodes, next 12 are unused for now
-    // the rest are u16s detailing connection info
+    // the header u16 is first 4 num internal nodes, next 4 are unused for now,
+    // last 8 are the mutation rate (Genes::mut_scale)
+    // then any number of u16s detailing connection info
     // x0 = from_internal. x1,x2,x3 = from_idx. x4 = to_internal. x5,x6,x7 = to_idx
     // x8-x15 is the weight of the connection (-4.0..4.0)
-    pub fn new(genes: Genes) -> Network {
-        let gene = genes.gene;
-        let n_internal = ((gene & (FOUR_BITS << 124)) >> 124) as usize; // 11110000...
-
-        let gen_synaps = |gene: u128, offset: u8| -> (bool, bool, Synapse) {
-            let off = (7 - offset) * 16;
-            let mask = ((gene & (SXTEEN_BITS << off)) >> off) as u16;
-            // get first bit
-            let from_internal = mask >= 1u16 << 15;
-            // get bits 2-4
-            let from = ((mask & (7u16 << 14)) >> 14) as u8;
-            // get 5th bit
-            let to_internal = mask & (1u16 << 11) != 0u16;
-            // get bits 6-8
-            let to = ((mask & (7u16 << 9)) >> 9) as u8;
-
-            // get last ten bits
-            // normalized to [-4.0..4.0]
-            // TODO: consider cubing for accuracy
-            let weight = (mask & 255u16) as i8 as f32 / 32.;
-
-            (from_internal, to_internal, Synapse { from, to, weight })
-        };
+    // changing any of this needs a new genome version in migrate.rs
+    pub fn new(genes: &Genes) -> Network {
+        let n_internal = (genes.header >> 12) as usize; // 11110000...
+
         // input to output or internal to output
         let mut int_out_synaps = Vec::new();
         let mut direct_synaps = Vec::new();
@@ -87,33 +217,17 @@ impl Network {
         // internal to other internal
         let mut int_synaps = Vec::new();
         // remove synaps that are conected to neurons that don't exist
-        for i in 1..8 {
-            let (from_int, to_int, syn) = gen_synaps(gene, i);
-            if to_int {
-                if from_int {
-                    if syn.from == syn.to {
-                        if syn.from < n_internal as u8 {
-                            self_synaps.push(syn);
-                        }
-                    } else {
-                        if syn.from < n_internal as u8 && syn.to < n_internal as u8 {
-                            int_synaps.push(syn);
-                        }
-                    }
-                } else {
-                    if syn.from < N_INPUT && syn.to < n_internal as u8 {
-                        to_int_synaps.push(syn);
-                    }
-                }
-            } else if from_int {
-                if syn.from < n_internal as u8 && syn.to < N_OUTPUT {
-                    int_out_synaps.push(syn)
-                }
-            } else {
-                if syn.from < N_INPUT && syn.to < N_OUTPUT {
-                    direct_synaps.push(syn);
-                }
-            }
+        for gene in &genes.synapses {
+            let decoded = DecodedSynapse::new(*gene);
+            let bundle = match decoded.kind(n_internal) {
+                Some(SynapseKind::Direct) => &mut direct_synaps,
+                Some(SynapseKind::IntOut) => &mut int_out_synaps,
+                Some(SynapseKind::ToInt) => &mut to_int_synaps,
+                Some(SynapseKind::SelfLoop) => &mut self_synaps,
+                Some(SynapseKind::Int) => &mut int_synaps,
+                None => continue,
+            };
+            bundle.push(decoded.synapse());
         }
 
         // TODO: remove synaps that do not eventually connect to an output
@@ -126,27 +240,27 @@ impl Network {
             int_synaps,
         };
 
-        let inputs = Vec::from_iter(
-            std::iter::repeat(Neuron {
+        let inputs = vec![
+            Neuron {
                 weight: 0.,
                 cur_sum: 0.,
-            })
-            .take(N_INPUT as usize),
-        );
-        let internal = Vec::from_iter(
-            std::iter::repeat(Neuron {
+            };
+            N_INPUT as usize
+        ];
+        let internal = vec![
+            Neuron {
                 weight: 0.5,
                 cur_sum: 0.,
-            })
-            .take(n_internal),
-        );
-        let outputs = Vec::from_iter(
-            std::iter::repeat(Neuron {
+            };
+            n_internal
+        ];
+        let outputs = vec![
+            Neuron {
                 weight: 0.,
                 cur_sum: 0.,
-            })
-            .take(N_OUTPUT as usize),
-        );
+            };
+            N_OUTPUT as usize
+        ];
 
         // TODO: consider separating out synapses to internal neurons and self loops
 
@@ -155,9 +269,39 @@ impl Network {
             internal,
             outputs,
             s_bundle,
+            leak: None,
+        }
+    }
+
+    // internal neurons remember across ticks, see BrainConfig
+    pub fn with_memory(self, leak: f32) -> Network {
+        Network {
+            leak: Some(leak),
+            ..self
         }
     }
 
+    // how many internal neurons it was built with
+    pub fn n_internal(&self) -> usize {
+        self.internal.len()
+    }
+
+    // every synapse it kept, as (from, to, weight), in the order eval goes through them
+    pub fn synapses(&self) -> Vec<(Node, Node, f32)> {
+        let b = &self.s_bundle;
+        let bundles = [
+            (&b.to_int_synaps, Node::Input as fn(u8) -> Node, Node::Internal as fn(u8) -> Node),
+            (&b.self_synaps, Node::Internal, Node::Internal),
+            (&b.int_synaps, Node::Internal, Node::Internal),
+            (&b.int_out_synaps, Node::Internal, Node::Output),
+            (&b.direct_synaps, Node::Input, Node::Output),
+        ];
+        bundles
+            .into_iter()
+            .flat_map(|(syns, from, to)| syns.iter().map(move |s| (from(s.from), to(s.to), s.weight)))
+            .collect()
+    }
+
     pub fn eval(&mut self) -> (f32, f32, bool, bool) {
         // go over all synapses
         // TODO: determine execution order, and if activate in between
@@ -205,8 +349,13 @@ impl Network {
         for neuron in &mut self.inputs {
             neuron.cur_sum = 0.;
         }
+        let keep = self.leak.map_or(0., |leak| 1. - leak);
         for neuron in &mut self.internal {
-            neuron.cur_sum = 0.;
+            neuron.cur_sum = if self.leak.is_some() {
+                neuron.cur_sum * keep
+            } else {
+                0.
+            };
         }
         for neuron in &mut self.outputs {
             neuron.cur_sum = 0.;
@@ -226,17 +375,15 @@ impl Network {
 
 #[cfg(test)]
 mod tests {
-    use crate::genes::Genes;
+    use crate::genes::{test_genes, Genes};
     use crate::network::Network;
 
     const EPSILON: f32 = 0.0001;
 
     #[test]
     fn convert_gene() {
-        // 1110000000000000 0_011_1_001_01101011 1_001_0_010_10000000 0000....
-        let mut test_net = Network::new(Genes {
-            gene: 297748235675921506640778121573503598592u128,
-        });
+        // 1110000000000000 0_000_1_100_01101011 1_010_0_001_10000000 0000....
+        let mut test_net = Network::new(&test_genes());
 
         // assert_eq!(test_net.eval(), (0.5, 0.62245935, false, false));
         assert_eq!(test_net.eval(), (0.0, -0.7615942, false, false));
@@ -246,4 +393,46 @@ mod tests {
         assert!((test_net.s_bundle.to_int_synaps[0].weight).abs() - 3.34375 <= EPSILON);
         assert!((test_net.s_bundle.int_out_synaps[0].weight).abs() - 4.0 <= EPSILON);
     }
+
+    #[test]
+    fn decodes_any_length() {
+        let genes = test_genes();
+        let mut longer = genes.clone();
+        longer.synapses.extend_from_slice(&genes.synapses[..2]);
+        let net = Network::new(&longer);
+        assert_eq!(net.s_bundle.int_out_synaps.len(), 2);
+        assert_eq!(net.s_bundle.to_int_synaps.len(), 2);
+
+        let empty = Genes {
+            header: genes.header,
+            synapses: vec![],
+        };
+        let mut net = Network::new(&empty);
+        assert_eq!(net.eval(), (0.0, 0.0, false, false));
+    }
+
+    #[test]
+    fn outputs_remember_earlier_ticks() {
+        // 1 internal, input:chem-x -> internal:0 w=1 and internal:0 -> output:move-x w=1
+        let genes = Genes {
+            header: 0x1080,
+            synapses: vec![0x0820, 0x8020],
+        };
+        // move-x on the second tick, with chem-x at `first` on the first one and 0 after
+        let second = |mut net: Network, first: f32| {
+            net.inputs[0].weight = first;
+            net.eval();
+            net.inputs[0].weight = 0.;
+            net.eval().0
+        };
+        let forgets = Network::new(&genes);
+        assert_eq!(second(forgets.clone(), 1.), second(forgets, 0.));
+
+        let remembers = Network::new(&genes).with_memory(0.25);
+        let sigmoid = |x: f32| 1. / (1. + (-x).exp());
+        // internal:0 still holds 1 * 0.75 from the first tick
+        let expected = 2. * sigmoid(sigmoid(0.75)) - 1.;
+        assert!((second(remembers.clone(), 1.) - expected).abs() < EPSILON);
+        assert!(second(remembers.clone(), 1.) > second(remembers, 0.));
+    }
 }
@@ -0,0 +1,267 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::Write as _,
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::lineage::CSV_HEADER;
+
+// One blob in the tree, from its rows in lineage.csv
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Node {
+    pub id: u64,
+    // every parent, mated blobs have two
+    pub parents: Vec<u64>,
+    pub generation: u16,
+    pub genome: String,
+    pub born: u64,
+    // None if it was still alive when the log ended
+    pub died: Option<u64>,
+    // starved or exhausted
+    pub cause: Option<String>,
+    pub birth_energy: f32,
+    pub peak_energy: Option<f32>,
+    pub age: Option<f32>,
+    pub offspring: Option<u32>,
+    pub food_eaten: Option<u32>,
+    // ids of the nodes below it in the tree
+    pub children: Vec<u64>,
+}
+
+// The family tree of a run. Mated blobs hang under their first parent, the one
+// that went looking for a partner, the other parent is only kept in `parents`.
+// Spawned blobs and blobs whose parents aren't in the log are roots
+#[derive(Debug, Default, Serialize)]
+pub struct Phylogeny {
+    // last tick in the log
+    pub end: u64,
+    pub roots: Vec<u64>,
+    pub nodes: BTreeMap<u64, Node>,
+}
+
+impl Phylogeny {
+    pub fn read(path: &Path) -> Result<Phylogeny, Box<dyn Error>> {
+        Phylogeny::parse(&fs::read_to_string(path)?)
+    }
+
+    // a lineage.csv written by LineagePlugin
+    pub fn parse(text: &str) -> Result<Phylogeny, Box<dyn Error>> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header.trim() == CSV_HEADER => {}
+            _ => return Err("not a lineage log, the header doesn't match".into()),
+        }
+        let mut tree = Phylogeny::default();
+        for (i, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            tree.add_row(line.trim())
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+
+        // ids only ever go up, so parents come before their children
+        let ids: Vec<u64> = tree.nodes.keys().copied().collect();
+        for id in ids {
+            match tree.nodes[&id].parents.first().copied() {
+                Some(parent) if tree.nodes.contains_key(&parent) => {
+                    tree.nodes.get_mut(&parent).unwrap().children.push(id)
+                }
+                _ => tree.roots.push(id),
+            }
+        }
+        Ok(tree)
+    }
+
+    fn add_row(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != CSV_HEADER.split(',').count() {
+            return Err(format!("expected {} fields", CSV_HEADER.split(',').count()).into());
+        }
+        let tick: u64 = fields[1].parse()?;
+        let id: u64 = fields[2].parse()?;
+        self.end = self.end.max(tick);
+        if fields[0] == "born" {
+            let parents = fields[3]
+                .split(';')
+                .filter(|p| !p.is_empty())
+                .map(|p| p.parse())
+                .collect::<Result<_, _>>()?;
+            self.nodes.insert(
+                id,
+                Node {
+                    id,
+                    parents,
+                    generation: fields[4].parse()?,
+                    genome: fields[5].to_string(),
+                    born: tick,
+                    birth_energy: fields[6].parse()?,
+                    ..Default::default()
+                },
+            );
+            return Ok(());
+        }
+
+        let node = self
+            .nodes
+            .get_mut(&id)
+            .ok_or_else(|| format!("blob {} wasn't born", id))?;
+        match fields[0] {
+            "starved" | "exhausted" => {
+                node.died = Some(tick);
+                node.cause = Some(fields[0].to_string());
+            }
+            "alive" => {}
+            event => return Err(format!("unknown event {}", event).into()),
+        }
+        node.age = Some(fields[7].parse()?);
+        node.offspring = Some(fields[8].parse()?);
+        node.food_eaten = Some(fields[9].parse()?);
+        node.peak_energy = Some(fields[10].parse()?);
+        Ok(())
+    }
+
+    // Only the blobs alive at the end and their ancestors
+    pub fn pruned(&self) -> Phylogeny {
+        let mut keep: BTreeMap<u64, Node> = BTreeMap::new();
+        // children first
+        for node in self.nodes.values().rev() {
+            let children: Vec<u64> = node
+                .children
+                .iter()
+                .copied()
+                .filter(|c| keep.contains_key(c))
+                .collect();
+            if node.died.is_none() || !children.is_empty() {
+                keep.insert(
+                    node.id,
+                    Node {
+                        children,
+                        ..node.clone()
+                    },
+                );
+            }
+        }
+        Phylogeny {
+            end: self.end,
+            roots: self
+                .roots
+                .iter()
+                .copied()
+                .filter(|r| keep.contains_key(r))
+                .collect(),
+            nodes: keep,
+        }
+    }
+
+    // Newick with every blob as a node named by its id, founders joined under one
+    // root. Branch lengths are ticks between the parent's birth and the child's,
+    // nodes are annotated NHX style with generation, birth and death tick.
+    // Built without recursion, lineages can be thousands of generations deep
+    pub fn newick(&self) -> String {
+        enum Step {
+            Open(u64, u64),
+            Close(u64, u64),
+            Comma,
+        }
+        let mut out = String::from("(");
+        let mut steps: Vec<Step> = Vec::new();
+        for (i, root) in self.roots.iter().rev().enumerate() {
+            if i > 0 {
+                steps.push(Step::Comma);
+            }
+            steps.push(Step::Open(*root, 0));
+        }
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Comma => out.push(','),
+                Step::Open(id, from) if !self.nodes[&id].children.is_empty() => {
+                    out.push('(');
+                    steps.push(Step::Close(id, from));
+                    let node = &self.nodes[&id];
+                    for (i, child) in node.children.iter().rev().enumerate() {
+                        if i > 0 {
+                            steps.push(Step::Comma);
+                        }
+                        steps.push(Step::Open(*child, node.born));
+                    }
+                }
+                Step::Open(id, from) => self.newick_label(&mut out, id, from),
+                Step::Close(id, from) => {
+                    out.push(')');
+                    self.newick_label(&mut out, id, from);
+                }
+            }
+        }
+        out.push_str(");\n");
+        out
+    }
+
+    fn newick_label(&self, out: &mut String, id: u64, from: u64) {
+        let node = &self.nodes[&id];
+        let _ = write!(
+            out,
+            "{}:{}[&&NHX:gen={}:born={}",
+            id,
+            node.born - from,
+            node.generation,
+            node.born
+        );
+        if let Some(died) = node.died {
+            let _ = write!(out, ":died={}", died);
+        }
+        out.push(']');
+    }
+
+    // <out>/phylogeny.nwk and phylogeny.json, the json has everything known per node
+    pub fn write(&self, out: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(out.join("phylogeny.nwk"), self.newick())?;
+        serde_json::to_writer_pretty(
+            BufWriter::new(File::create(out.join("phylogeny.json"))?),
+            self,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lineage::CSV_HEADER, phylogeny::Phylogeny};
+
+    #[test]
+    fn builds_and_prunes() {
+        let log = format!(
+            "{}\n\
+            born,0,0,,0,blob1:0000,100,,,,\n\
+            born,0,1,,0,blob1:0001,100,,,,\n\
+            born,60,2,0,1,blob1:0002,50,,,,\n\
+            born,120,3,2;1,2,blob1:0003,25,,,,\n\
+            born,120,4,0,1,blob1:0004,50,,,,\n\
+            starved,150,4,,1,,,0.5,0,0,50\n\
+            starved,180,0,,0,,,1.8,2,3,120\n\
+            alive,200,1,,0,,80,2,1,2,100\n",
+            CSV_HEADER
+        );
+        let tree = Phylogeny::parse(&log).unwrap();
+        assert_eq!(tree.end, 200);
+        assert_eq!(tree.roots, vec![0, 1]);
+        assert_eq!(tree.nodes[&3].parents, vec![2, 1]);
+        assert_eq!(tree.nodes[&0].peak_energy, Some(120.));
+        assert_eq!(
+            tree.newick(),
+            "(((3:60[&&NHX:gen=2:born=120])2:60[&&NHX:gen=1:born=60],\
+            4:120[&&NHX:gen=1:born=120:died=150])0:0[&&NHX:gen=0:born=0:died=180],\
+            1:0[&&NHX:gen=0:born=0]);\n"
+        );
+
+        // 4 died without children, 0 stays as the ancestor of 3
+        let pruned = tree.pruned();
+        assert_eq!(pruned.nodes.keys().copied().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        assert_eq!(pruned.nodes[&0].children, vec![2]);
+
+        assert!(Phylogeny::parse("tick,blobs\n").is_err());
+        assert!(Phylogeny::parse(&format!("{}\nstarved,1,9,,0,,,1,0,0,1\n", CSV_HEADER)).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use bevy::{
+    math::Vec2,
+    prelude::{Component, Entity, Query, Res, Transform},
+    tasks::ComputeTaskPool,
+};
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+use crate::{
+    config::PhysicsConfig,
+    rng::{SimRng, Stream},
+    Tick,
+};
+
+// Size of the world, everything wraps around at the edges
+pub struct WinSize {
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Component)]
+pub struct Velocity(pub Vec2);
+impl Default for Velocity {
+    fn default() -> Self {
+        Self(Vec2::ZERO)
+    }
+}
+
+#[derive(Component)]
+pub struct Acceleration(pub Vec2);
+impl Default for Acceleration {
+    fn default() -> Self {
+        Self(Vec2::ZERO)
+    }
+}
+
+// Moves all entities according to Brownian motion to simulate the movement of water
+// Also takes other movement into account
+pub fn brownian_drift(
+    win: Res<WinSize>,
+    physics: Res<PhysicsConfig>,
+    rng: Res<SimRng>,
+    tick: Res<Tick>,
+    mut query: Query<(Entity, &mut Acceleration, &mut Velocity, &mut Transform)>,
+    pool: Res<ComputeTaskPool>,
+) {
+    query.par_for_each_mut(&pool, 128, |(ent, mut accel, mut vel, mut trans)| {
+        // Brownian
+        let mut r = rng.entity_stream(Stream::Drift, tick.0, ent);
+        // maybe try perlin noise?
+        accel.0.x += r.sample::<f32, _>(StandardNormal) / physics.brown_scale;
+        accel.0.y += r.sample::<f32, _>(StandardNormal) / physics.brown_scale;
+
+        vel.0.x += accel.0.x;
+        vel.0.y += accel.0.y;
+
+        trans.translation.x = ((trans.translation.x + vel.0.x) % win.w + win.w) % win.w;
+        trans.translation.y = ((trans.translation.y + vel.0.y) % win.h + win.h) % win.h;
+
+        // drag
+        vel.0.x *= physics.drag;
+        vel.0.y *= physics.drag;
+
+        accel.0.x = 0.;
+        accel.0.y = 0.;
+    });
+}
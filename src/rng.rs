@@ -0,0 +1,78 @@
+use bevy::prelude::Entity;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+// One seed for the whole sim, so a run can be replayed bit for bit.
+// Generators are derived from (seed, stream, tick) instead of being stored and
+// shared, so systems that run in parallel (and par_for_each_mut) never race
+// over who draws next
+pub struct SimRng {
+    pub seed: u64,
+}
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    // generator for a whole system this tick
+    pub fn stream(&self, stream: Stream, tick: u64) -> ChaCha8Rng {
+        self.derive(stream as u64, tick)
+    }
+
+    // generator for one entity this tick, for use inside par_for_each_mut
+    pub fn entity_stream(&self, stream: Stream, tick: u64, ent: Entity) -> ChaCha8Rng {
+        self.derive((stream as u64) << 32 | ent.id() as u64, tick)
+    }
+
+    fn derive(&self, stream: u64, tick: u64) -> ChaCha8Rng {
+        let mut r = ChaCha8Rng::seed_from_u64(self.seed);
+        r.set_stream(stream);
+        // 2^32 words per tick is far more than any system draws
+        r.set_word_pos((tick as u128) << 32);
+        r
+    }
+}
+
+// every system that draws random numbers gets its own stream
+#[derive(Clone, Copy)]
+pub enum Stream {
+    Drift = 1,
+    SpawnFood,
+    EmitChems,
+    SpawnBlobs,
+    Replicate,
+    SeedBlobs,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rng::{SimRng, Stream};
+    use bevy::prelude::Entity;
+    use rand::Rng;
+
+    #[test]
+    fn streams_are_reproducible() {
+        let a = SimRng::new(42);
+        let b = SimRng::new(42);
+        let draw = |rng: &SimRng, stream, tick| rng.stream(stream, tick).gen::<u64>();
+
+        assert_eq!(draw(&a, Stream::Drift, 7), draw(&b, Stream::Drift, 7));
+        assert_ne!(draw(&a, Stream::Drift, 7), draw(&a, Stream::Drift, 8));
+        assert_ne!(draw(&a, Stream::Drift, 7), draw(&a, Stream::EmitChems, 7));
+        assert_ne!(
+            draw(&a, Stream::Drift, 7),
+            draw(&SimRng::new(43), Stream::Drift, 7)
+        );
+
+        let e1 = Entity::from_raw(1);
+        let e2 = Entity::from_raw(2);
+        assert_eq!(
+            a.entity_stream(Stream::Drift, 3, e1).gen::<u64>(),
+            b.entity_stream(Stream::Drift, 3, e1).gen::<u64>()
+        );
+        assert_ne!(
+            a.entity_stream(Stream::Drift, 3, e1).gen::<u64>(),
+            a.entity_stream(Stream::Drift, 3, e2).gen::<u64>()
+        );
+    }
+}
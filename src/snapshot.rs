@@ -0,0 +1,467 @@
+use std::{error::Error, fs::File, io::BufWriter, path::PathBuf};
+
+use bevy::{
+    input::Input,
+    log::{error, info},
+    math::{Vec2, Vec3},
+    prelude::{
+        App, Commands, CoreStage, EventReader, EventWriter, KeyCode,
+        ParallelSystemDescriptorCoercion, Plugin, Query, Res, ResMut, SystemLabel, Transform,
+        TransformBundle,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    brain::{Actions, BrainBuilder},
+    blob::{Blob, CurBlobs, NextBlobId},
+    food::{CurFood, Food},
+    genes::Genes,
+    hall::HallOfFame,
+    network::Network,
+    rng::SimRng,
+    species::{SpeciesId, SpeciesRegistry},
+    Acceleration, Chem, OutDir, SimLabel, Tick, Velocity,
+};
+
+// bump this whenever the layout below changes
+const SNAPSHOT_VERSION: u32 = 11;
+
+// Everything needed to pick a world back up where it was left.
+// The RNG has no state besides its seed, every stream is derived from the tick.
+// Entities get new ids when restored, so a resumed run is reproducible from the
+// snapshot but won't match the run that never stopped draw for draw
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    pub seed: u64,
+    tick: u64,
+    cur_blobs: u32,
+    next_id: u64,
+    cur_food: u32,
+    hall: HallOfFame,
+    species: SpeciesRegistry,
+    blobs: Vec<BlobState>,
+    food: Vec<FoodState>,
+    chems: Vec<ChemState>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Motion {
+    translation: Vec3,
+    velocity: Vec2,
+    acceleration: Vec2,
+}
+impl Motion {
+    fn new(trans: &Transform, vel: &Velocity, accel: &Acceleration) -> Self {
+        Motion {
+            translation: trans.translation,
+            velocity: vel.0,
+            acceleration: accel.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BlobState {
+    motion: Motion,
+    energy: f32,
+    age: f32,
+    generation: u16,
+    genes: Genes,
+    // None for brains that aren't a Network, they are built from the genes again
+    brain: Option<Network>,
+    actions: Actions,
+    #[serde(default)]
+    exhausted: bool,
+    offspring: u32,
+    food_eaten: u32,
+    peak_energy: f32,
+    id: u64,
+    parents: Vec<u64>,
+    born: u64,
+    birth_energy: f32,
+    // None if it was born since the last census
+    species: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FoodState {
+    motion: Motion,
+    nutrition: f32,
+    chem_id: u8,
+    emit_life: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChemState {
+    motion: Motion,
+    id: u8,
+    dissolve_life: u16,
+}
+
+impl Snapshot {
+    // the last tick simulated before it was saved
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn read(path: &PathBuf) -> Result<Snapshot, Box<dyn Error>> {
+        let snap: Snapshot = ron::de::from_reader(File::open(path)?)?;
+        if snap.version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot version {} is not supported (expected {})",
+                snap.version, SNAPSHOT_VERSION
+            )
+            .into());
+        }
+        Ok(snap)
+    }
+
+    pub fn write(&self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        ron::ser::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+// Ask for the world to be written to a file at the start of the next tick
+pub struct SaveSnapshot(pub PathBuf);
+
+// Write a snapshot every n ticks
+pub struct AutoSave(pub u64);
+
+pub struct SnapshotPlugin;
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveSnapshot>()
+            .add_startup_system(restore_snapshot.label(SimLabel::Restore))
+            // the previous tick is fully applied here, and the next hasn't started
+            .add_system_to_stage(
+                CoreStage::First,
+                autosave.label(SaveLabel).before(SimLabel::Tick),
+            )
+            .add_system_to_stage(
+                CoreStage::First,
+                save_snapshot.after(SaveLabel).before(SimLabel::Tick),
+            );
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+struct SaveLabel;
+
+fn snapshot_path(out: &OutDir, tick: u64) -> PathBuf {
+    out.0.join(format!("snapshot-{:08}.ron", tick))
+}
+
+fn autosave(
+    tick: Res<Tick>,
+    every: Option<Res<AutoSave>>,
+    out: Res<OutDir>,
+    mut saves: EventWriter<SaveSnapshot>,
+) {
+    if let Some(every) = every {
+        if tick.0 > 0 && tick.0 % every.0 == 0 {
+            saves.send(SaveSnapshot(snapshot_path(&out, tick.0)));
+        }
+    }
+}
+
+// F5 saves while watching
+pub fn save_on_key(
+    keys: Res<Input<KeyCode>>,
+    tick: Res<Tick>,
+    out: Res<OutDir>,
+    mut saves: EventWriter<SaveSnapshot>,
+) {
+    if keys.just_pressed(KeyCode::F5) {
+        saves.send(SaveSnapshot(snapshot_path(&out, tick.0)));
+    }
+}
+
+type SavedBlob<'a> = (
+    &'a Blob,
+    &'a Genes,
+    Option<&'a SpeciesId>,
+    &'a Transform,
+    &'a Velocity,
+    &'a Acceleration,
+);
+
+#[allow(clippy::too_many_arguments)]
+fn save_snapshot(
+    mut saves: EventReader<SaveSnapshot>,
+    rng: Res<SimRng>,
+    tick: Res<Tick>,
+    cur_blobs: Res<CurBlobs>,
+    next_id: Res<NextBlobId>,
+    cur_food: Res<CurFood>,
+    hall: Res<HallOfFame>,
+    species: Res<SpeciesRegistry>,
+    blob_query: Query<SavedBlob>,
+    food_query: Query<(&Food, &Transform, &Velocity, &Acceleration)>,
+    chem_query: Query<(&Chem, &Transform, &Velocity, &Acceleration)>,
+) {
+    let paths: Vec<PathBuf> = saves.iter().map(|save| save.0.clone()).collect();
+    if paths.is_empty() {
+        return;
+    }
+
+    let snap = Snapshot {
+        version: SNAPSHOT_VERSION,
+        seed: rng.seed,
+        tick: tick.0,
+        cur_blobs: cur_blobs.0,
+        next_id: next_id.0,
+        cur_food: cur_food.0,
+        hall: hall.clone(),
+        species: species.clone(),
+        blobs: blob_query
+            .iter()
+            .map(|(blob, genes, species, trans, vel, accel)| BlobState {
+                motion: Motion::new(trans, vel, accel),
+                energy: blob.energy,
+                age: blob.age,
+                generation: blob.generation,
+                genes: genes.clone(),
+                brain: blob.brain.network().cloned(),
+                actions: blob.actions,
+                exhausted: blob.exhausted,
+                offspring: blob.offspring,
+                food_eaten: blob.food_eaten,
+                peak_energy: blob.peak_energy,
+                id: blob.id,
+                parents: blob.parents.clone(),
+                born: blob.born,
+                birth_energy: blob.birth_energy,
+                species: species.map(|species| species.0),
+            })
+            .collect(),
+        food: food_query
+            .iter()
+            .map(|(food, trans, vel, accel)| FoodState {
+                motion: Motion::new(trans, vel, accel),
+                nutrition: food.nutriton,
+                chem_id: food.chem_id,
+                emit_life: food.emit_life,
+            })
+            .collect(),
+        chems: chem_query
+            .iter()
+            .map(|(chem, trans, vel, accel)| ChemState {
+                motion: Motion::new(trans, vel, accel),
+                id: chem.id,
+                dissolve_life: chem.dissolve_life,
+            })
+            .collect(),
+    };
+
+    for path in paths {
+        match snap.write(&path) {
+            Ok(()) => info!("Saved snapshot to {}", path.display()),
+            Err(e) => error!("Could not save snapshot to {}: {}", path.display(), e),
+        }
+    }
+}
+
+// Fills the empty world with the one in the Snapshot resource, if there is one
+#[allow(clippy::too_many_arguments)]
+fn restore_snapshot(
+    mut commands: Commands,
+    snap: Option<Res<Snapshot>>,
+    mut cur_blobs: ResMut<CurBlobs>,
+    mut next_id: ResMut<NextBlobId>,
+    mut cur_food: ResMut<CurFood>,
+    mut hall: ResMut<HallOfFame>,
+    mut species: ResMut<SpeciesRegistry>,
+    brains: Res<BrainBuilder>,
+) {
+    let snap = match snap {
+        Some(snap) => snap,
+        None => return,
+    };
+    let spawn = |commands: &mut Commands, motion: &Motion| {
+        commands
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(motion.translation),
+            ))
+            .insert(Velocity(motion.velocity))
+            .insert(Acceleration(motion.acceleration))
+            .id()
+    };
+    for state in &snap.blobs {
+        let ent = spawn(&mut commands, &state.motion);
+        commands
+            .entity(ent)
+            .insert(Blob {
+                energy: state.energy,
+                age: state.age,
+                generation: state.generation,
+                brain: match &state.brain {
+                    Some(net) => Box::new(net.clone()),
+                    None => brains.build(&state.genes),
+                },
+                actions: state.actions,
+                exhausted: state.exhausted,
+                offspring: state.offspring,
+                food_eaten: state.food_eaten,
+                peak_energy: state.peak_energy,
+                id: state.id,
+                parents: state.parents.clone(),
+                born: state.born,
+                birth_energy: state.birth_energy,
+            })
+            .insert(state.genes.clone());
+        if let Some(species) = state.species {
+            commands.entity(ent).insert(SpeciesId(species));
+        }
+    }
+    for state in &snap.food {
+        let ent = spawn(&mut commands, &state.motion);
+        commands.entity(ent).insert(Food {
+            nutriton: state.nutrition,
+            chem_id: state.chem_id,
+            emit_life: state.emit_life,
+        });
+    }
+    for state in &snap.chems {
+        let ent = spawn(&mut commands, &state.motion);
+        commands.entity(ent).insert(Chem {
+            id: state.id,
+            dissolve_life: state.dissolve_life,
+        });
+    }
+
+    cur_blobs.0 += snap.cur_blobs;
+    next_id.0 = next_id.0.max(snap.next_id);
+    cur_food.0 += snap.cur_food;
+    *hall = snap.hall.clone();
+    *species = snap.species.clone();
+    commands.remove_resource::<Snapshot>();
+    info!(
+        "Restored snapshot at tick {}: {} blobs, {} food, {} chems",
+        snap.tick,
+        snap.blobs.len(),
+        snap.food.len(),
+        snap.chems.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        app::StartupSchedule,
+        ecs::{event::Events, schedule::Stage},
+        prelude::{App, CoreStage, Schedule, SystemStage},
+        MinimalPlugins,
+    };
+    use serde::Serialize;
+
+    use crate::{
+        blob::Blob,
+        snapshot::{SaveSnapshot, Snapshot},
+        Config, SimulationPlugin, Tick,
+    };
+
+    // every entity's state as text, in an order that doesn't depend on entity ids
+    fn sorted<T: Serialize>(states: &[T]) -> Vec<String> {
+        let mut states: Vec<String> = states
+            .iter()
+            .map(|state| ron::to_string(state).unwrap())
+            .collect();
+        states.sort();
+        states
+    }
+
+    #[test]
+    fn restores_what_it_saved() {
+        let dir = std::env::temp_dir().join(format!("blobs-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (saved, resaved) = (dir.join("saved.ron"), dir.join("resaved.ron"));
+        let mut config = Config::default();
+        config.species.interval = 50;
+        let plugin = |config: &Config| SimulationPlugin {
+            config: config.clone(),
+            seed: 5,
+            out: dir.clone(),
+        };
+
+        let mut original = App::new();
+        original
+            .add_plugins(MinimalPlugins)
+            .add_plugin(plugin(&config));
+        for _ in 0..120 {
+            original.update();
+        }
+        original
+            .world
+            .resource_mut::<Events<SaveSnapshot>>()
+            .send(SaveSnapshot(saved.clone()));
+        // saves before it simulates the next tick
+        original.update();
+        let a = Snapshot::read(&saved).unwrap();
+
+        // the restored world saved again before it simulates anything
+        let mut app = App::new();
+        app.insert_resource(Snapshot::read(&saved).unwrap())
+            .add_plugins(MinimalPlugins)
+            .add_plugin(plugin(&config));
+        app.schedule
+            .stage(StartupSchedule, |startup: &mut Schedule| {
+                startup.run(&mut app.world);
+                startup
+            });
+        app.world
+            .resource_mut::<Events<SaveSnapshot>>()
+            .send(SaveSnapshot(resaved.clone()));
+        app.schedule
+            .stage(CoreStage::First, |first: &mut SystemStage| {
+                first.run(&mut app.world);
+                first
+            });
+        let b = Snapshot::read(&resaved).unwrap();
+
+        // and one that is left to run simulates the same next tick the original did
+        let mut resumed = App::new();
+        resumed
+            .insert_resource(Snapshot::read(&saved).unwrap())
+            .add_plugins(MinimalPlugins)
+            .add_plugin(plugin(&config));
+        resumed.update();
+        let _ = std::fs::remove_dir_all(&dir);
+        let ages = |app: &mut App| {
+            let mut ages: Vec<(u64, u32)> = app
+                .world
+                .query::<&Blob>()
+                .iter(&app.world)
+                .filter(|blob| blob.born <= a.tick)
+                .map(|blob| (blob.id, blob.age.to_bits()))
+                .collect();
+            ages.sort();
+            ages
+        };
+        assert_eq!(original.world.resource::<Tick>().0, a.tick + 1);
+        assert_eq!(resumed.world.resource::<Tick>().0, a.tick + 1);
+        assert!(!ages(&mut resumed).is_empty());
+        assert_eq!(ages(&mut original), ages(&mut resumed));
+
+        assert!(!a.blobs.is_empty() && !a.food.is_empty() && !a.chems.is_empty());
+        assert!(a.blobs.iter().any(|blob| blob.species.is_some()));
+        assert_eq!(b.tick, a.tick);
+        assert_eq!(
+            (a.seed, a.cur_blobs, a.next_id, a.cur_food),
+            (b.seed, b.cur_blobs, b.next_id, b.cur_food)
+        );
+        assert_eq!(
+            ron::to_string(&a.hall).unwrap(),
+            ron::to_string(&b.hall).unwrap()
+        );
+        assert_eq!(
+            ron::to_string(&a.species).unwrap(),
+            ron::to_string(&b.species).unwrap()
+        );
+        assert_eq!(sorted(&a.blobs), sorted(&b.blobs));
+        assert_eq!(sorted(&a.food), sorted(&b.food));
+        assert_eq!(sorted(&a.chems), sorted(&b.chems));
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use bevy::{
+    log::error,
+    prelude::{App, Commands, Component, CoreStage, Entity, Plugin, Query, Res, ResMut},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{blob::Blob, config::SpeciesConfig, genes::Genes, OutDir, Tick};
+
+// Which species a blob was put in at the last census, newborns have none until the next
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Component)]
+pub struct SpeciesId(pub u64);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Species {
+    pub id: u64,
+    // tick of the census that found it
+    pub founded: u64,
+    // genome of its lowest id member at the last census, so it follows the lineage as it drifts
+    pub representative: Genes,
+    pub population: u32,
+    pub peak_population: u32,
+}
+
+// What changed at a census
+#[derive(Default)]
+pub struct Census {
+    // species of each blob, in the order they were given
+    pub assigned: Vec<u64>,
+    pub founded: Vec<u64>,
+    pub extinct: Vec<Species>,
+}
+
+// Every species alive at the last census
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SpeciesRegistry {
+    next_id: u64,
+    pub living: Vec<Species>,
+}
+
+impl SpeciesRegistry {
+    // Puts every blob in the species with the closest representative, if it is within
+    // `threshold` bits, or founds a new one around it. Species left without members are
+    // extinct. Blobs should come in id order so the oldest member represents its species
+    pub fn census(&mut self, tick: u64, blobs: &[(u64, &Genes)], threshold: u32) -> Census {
+        let mut census = Census::default();
+        for species in &mut self.living {
+            species.population = 0;
+        }
+        for (_, genes) in blobs {
+            let closest = self
+                .living
+                .iter()
+                .enumerate()
+                .map(|(i, species)| (species.representative.distance(genes), i))
+                .min()
+                .filter(|(distance, _)| *distance <= threshold);
+            let species = match closest {
+                Some((_, i)) => &mut self.living[i],
+                None => {
+                    census.founded.push(self.next_id);
+                    self.living.push(Species {
+                        id: self.next_id,
+                        founded: tick,
+                        representative: (*genes).clone(),
+                        population: 0,
+                        peak_population: 0,
+                    });
+                    self.next_id += 1;
+                    self.living.last_mut().unwrap()
+                }
+            };
+            // representatives are from the previous census until someone is counted
+            if species.population == 0 {
+                species.representative = (*genes).clone();
+            }
+            species.population += 1;
+            species.peak_population = species.peak_population.max(species.population);
+            census.assigned.push(species.id);
+        }
+        let (living, extinct) = self.living.drain(..).partition(|s| s.population > 0);
+        self.living = living;
+        census.extinct = extinct;
+        census
+    }
+}
+
+struct SpeciesLog(BufWriter<File>);
+
+// Clusters the living blobs into species every species.interval ticks, tags them with
+// SpeciesId and, with species.log, writes every species at every census to
+// <out>/species.csv: new the first time it shows up, alive after that and extinct once
+// it has no members left
+pub struct SpeciesPlugin;
+impl Plugin for SpeciesPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpeciesRegistry::default())
+            .add_startup_system(open_species)
+            // after everything that changes blobs, like the hall of fame
+            .add_system_to_stage(CoreStage::PostUpdate, take_census);
+    }
+}
+
+fn open_species(mut commands: Commands, config: Res<SpeciesConfig>, out: Res<OutDir>) {
+    if !config.log {
+        return;
+    }
+    let path = out.0.join("species.csv");
+    match File::create(&path) {
+        Ok(file) => {
+            let mut out = BufWriter::new(file);
+            let _ = writeln!(out, "tick,event,species,population,founded,genome");
+            commands.insert_resource(SpeciesLog(out));
+        }
+        Err(e) => error!("Could not create {}: {}", path.display(), e),
+    }
+}
+
+fn take_census(
+    mut commands: Commands,
+    mut registry: ResMut<SpeciesRegistry>,
+    log: Option<ResMut<SpeciesLog>>,
+    config: Res<SpeciesConfig>,
+    tick: Res<Tick>,
+    query: Query<(Entity, &Blob, &Genes)>,
+) {
+    if tick.0 % config.interval != 0 {
+        return;
+    }
+    let mut blobs: Vec<_> = query.iter().collect();
+    blobs.sort_by_key(|(_, blob, _)| blob.id);
+    let ids: Vec<(u64, &Genes)> = blobs.iter().map(|(_, b, g)| (b.id, *g)).collect();
+    let census = registry.census(tick.0, &ids, config.threshold);
+    for ((ent, _, _), species) in blobs.iter().zip(&census.assigned) {
+        commands.entity(*ent).insert(SpeciesId(*species));
+    }
+
+    let mut log = match log {
+        Some(log) => log,
+        None => return,
+    };
+    let out = &mut log.0;
+    let written = (|| -> io::Result<()> {
+        for species in &registry.living {
+            let event = if census.founded.contains(&species.id) {
+                "new"
+            } else {
+                "alive"
+            };
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                tick.0,
+                event,
+                species.id,
+                species.population,
+                species.founded,
+                species.representative
+            )?;
+        }
+        for species in &census.extinct {
+            writeln!(
+                out,
+                "{},extinct,{},0,{},{}",
+                tick.0, species.id, species.founded, species.representative
+            )?;
+        }
+        out.flush()
+    })();
+    if let Err(e) = written {
+        error!("Could not write species: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{genes::Genes, species::SpeciesRegistry};
+
+    #[test]
+    fn clusters_and_goes_extinct() {
+        let a = Genes::from_u128(0);
+        let a2 = Genes::from_u128(0b11);
+        let b = Genes::from_u128(u128::MAX);
+        let mut registry = SpeciesRegistry::default();
+
+        let census = registry.census(0, &[(0, &a), (1, &b), (2, &a2)], 4);
+        assert_eq!(census.assigned, vec![0, 1, 0]);
+        assert_eq!(census.founded, vec![0, 1]);
+        assert_eq!(registry.living[0].population, 2);
+
+        // the a lineage drifts, its representative follows the oldest member
+        let a3 = Genes::from_u128(0b1111);
+        let census = registry.census(60, &[(2, &a2), (3, &a3)], 4);
+        assert_eq!(census.assigned, vec![0, 0]);
+        assert_eq!(registry.living[0].representative, a2);
+        assert_eq!(census.extinct.len(), 1);
+        assert_eq!(census.extinct[0].id, 1);
+        assert_eq!(registry.living.len(), 1);
+        assert_eq!(registry.living[0].peak_population, 2);
+    }
+}
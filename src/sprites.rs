@@ -0,0 +1,213 @@
+use std::{f32::consts::TAU, str::FromStr};
+
+use bevy::{
+    input::Input,
+    log::info,
+    math::Vec2,
+    prelude::{
+        Added, App, Color, Commands, CoreStage, Entity, Handle, Image, KeyCode,
+        OrthographicCameraBundle, Plugin, Query, Res, ResMut, Visibility,
+    },
+    sprite::Sprite,
+    window::Windows,
+};
+
+use crate::{
+    blob::Blob, config::BlobConfig, food::Food, genes::Genes, snapshot, species::SpeciesId, Chem,
+    WinSize,
+};
+
+// What a blob's colour shows, C cycles through them in the window
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    // hue from the genome bits, one bit flip only nudges it
+    #[default]
+    Genome,
+    // one hue per species, the genome hue until a blob's first census
+    Species,
+    // blue when empty to red at twice the starting energy
+    Energy,
+    Age,
+    // blue for the lowest generation alive to red for the highest
+    Generation,
+}
+impl ColorMode {
+    const ALL: [ColorMode; 5] = [
+        ColorMode::Genome,
+        ColorMode::Species,
+        ColorMode::Energy,
+        ColorMode::Age,
+        ColorMode::Generation,
+    ];
+
+    fn next(self) -> ColorMode {
+        let i = ColorMode::ALL.iter().position(|m| *m == self).unwrap();
+        ColorMode::ALL[(i + 1) % ColorMode::ALL.len()]
+    }
+}
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "genome" => Ok(ColorMode::Genome),
+            "species" => Ok(ColorMode::Species),
+            "energy" => Ok(ColorMode::Energy),
+            "age" => Ok(ColorMode::Age),
+            "generation" => Ok(ColorMode::Generation),
+            _ => Err(format!(
+                "unknown colour mode {}, expected genome, species, energy, age or generation",
+                s
+            )),
+        }
+    }
+}
+
+// Everything that needs a window: the camera, sizing the world to the window, and
+// giving entities something to draw once they are spawned, so the simulation
+// itself never has to know whether there is a renderer.
+// Blobs are coloured by the ColorMode resource, Genome unless one is inserted
+pub struct SpritesPlugin;
+impl Plugin for SpritesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ColorMode>()
+            .add_startup_system(setup)
+            .add_system(snapshot::save_on_key)
+            .add_system(cycle_color_mode)
+            .add_system_to_stage(CoreStage::PostUpdate, draw_blobs)
+            .add_system_to_stage(CoreStage::PostUpdate, color_blobs)
+            .add_system_to_stage(CoreStage::PostUpdate, draw_food)
+            .add_system_to_stage(CoreStage::PostUpdate, draw_chems);
+    }
+}
+
+fn setup(mut commands: Commands, win: Res<Windows>) {
+    let w = win.get_primary().unwrap();
+
+    // set origin to top left
+    let mut camera = OrthographicCameraBundle::new_2d();
+    camera.transform.translation.x += w.width() / 2.;
+    camera.transform.translation.y += w.height() / 2.;
+    commands.spawn_bundle(camera);
+    commands.insert_resource(WinSize {
+        w: w.width() - 5.,
+        h: w.height() - 5.,
+    });
+}
+
+fn insert_sprite(commands: &mut Commands, ent: Entity, color: Color, size: f32) {
+    commands
+        .entity(ent)
+        .insert(Sprite {
+            color,
+            custom_size: Some(Vec2::new(size, size)),
+            ..Default::default()
+        })
+        .insert(Handle::<Image>::default())
+        .insert(Visibility::default());
+}
+
+// Hue of the sum of one unit vector per genome bit, pointing the other way when the bit
+// is clear. Bit i's vector is i golden angles round, so every bit pulls somewhere
+// different and a single flip only turns the sum a little
+pub fn genome_hue(genes: &Genes) -> f32 {
+    let golden = TAU * (1. - 1. / 1.618_034);
+    let (mut x, mut y) = (0., 0.);
+    for (i, word) in genes.words().enumerate() {
+        for bit in 0..16 {
+            let angle = (i * 16 + bit) as f32 * golden;
+            let sign = if word & (1 << bit) != 0 { 1. } else { -1. };
+            x += sign * angle.cos();
+            y += sign * angle.sin();
+        }
+    }
+    (f32::atan2(y, x) / TAU * 360.).rem_euclid(360.)
+}
+
+fn genome_color(genes: &Genes) -> Color {
+    Color::hsl(genome_hue(genes), 0.8, 0.55)
+}
+
+// neighbouring ids get far apart hues
+fn species_color(id: u64) -> Color {
+    Color::hsl((id as f32 * 137.507_77).rem_euclid(360.), 0.8, 0.55)
+}
+
+// t from 0 to 1 goes blue to red
+fn gradient(t: f32) -> Color {
+    Color::hsl(240. * (1. - t.clamp(0., 1.)), 0.8, 0.5)
+}
+
+fn draw_blobs(mut commands: Commands, query: Query<(Entity, &Genes), Added<Genes>>) {
+    query.for_each(|(ent, genes)| {
+        insert_sprite(&mut commands, ent, genome_color(genes), 5.);
+    });
+}
+
+// every frame, energy and age change all the time
+fn color_blobs(
+    mode: Res<ColorMode>,
+    config: Res<BlobConfig>,
+    mut query: Query<(&mut Sprite, &Blob, &Genes, Option<&SpeciesId>)>,
+) {
+    let (mut gen_min, mut gen_max) = (u16::MAX, 0);
+    if *mode == ColorMode::Generation {
+        for (_, blob, _, _) in query.iter() {
+            gen_min = gen_min.min(blob.generation);
+            gen_max = gen_max.max(blob.generation);
+        }
+    }
+    query.for_each_mut(|(mut sprite, blob, genes, species)| {
+        sprite.color = match (*mode, species) {
+            (ColorMode::Genome, _) | (ColorMode::Species, None) => genome_color(genes),
+            (ColorMode::Species, Some(species)) => species_color(species.0),
+            (ColorMode::Energy, _) => gradient(blob.energy / (2. * config.start_energy)),
+            // most blobs don't live to 1
+            (ColorMode::Age, _) => gradient(1. - (-2. * blob.age).exp()),
+            (ColorMode::Generation, _) => gradient(
+                (blob.generation - gen_min) as f32 / (gen_max - gen_min).max(1) as f32,
+            ),
+        };
+    });
+}
+
+fn cycle_color_mode(keys: Res<Input<KeyCode>>, mut mode: ResMut<ColorMode>) {
+    if keys.just_pressed(KeyCode::C) {
+        *mode = mode.next();
+        info!("Colouring blobs by {:?}", *mode);
+    }
+}
+
+fn draw_food(mut commands: Commands, query: Query<Entity, Added<Food>>) {
+    query.for_each(|ent| {
+        insert_sprite(&mut commands, ent, Color::rgb(0., 1., 0.), 3.);
+    });
+}
+
+fn draw_chems(mut commands: Commands, query: Query<(Entity, &Chem), Added<Chem>>) {
+    query.for_each(|(ent, chem)| {
+        insert_sprite(
+            &mut commands,
+            ent,
+            Color::rgba_u8(chem.id, chem.id, chem.id, 123u8),
+            1.,
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{genes::test_genes, sprites::genome_hue};
+
+    #[test]
+    fn one_bit_barely_changes_hue() {
+        let genes = test_genes();
+        let hue = genome_hue(&genes);
+        for bit in 0..16 {
+            let mut mutant = genes.clone();
+            mutant.synapses[3] ^= 1 << bit;
+            let diff = (genome_hue(&mutant) - hue).abs();
+            assert!(diff.min(360. - diff) < 30., "{}", diff);
+        }
+    }
+}
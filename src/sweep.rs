@@ -0,0 +1,331 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch::{RunSpec, RunSummary, Spread},
+    config::ConfigError,
+    Config,
+};
+
+// Values one parameter takes in the grid
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Values {
+    List(Vec<f64>),
+    // `steps` evenly spaced values from `from` to `to`, both included
+    Linear(f64, f64, u32),
+    // same but evenly spaced on a log scale, for rates
+    Log(f64, f64, u32),
+}
+impl Values {
+    pub fn expand(&self) -> Vec<f64> {
+        // t runs from 0 to 1 over the steps
+        let spaced = |steps: u32, at: &dyn Fn(f64) -> f64| -> Vec<f64> {
+            match steps {
+                0 => vec![],
+                1 => vec![at(0.)],
+                _ => (0..steps)
+                    .map(|i| tidy(at(i as f64 / (steps - 1) as f64)))
+                    .collect(),
+            }
+        };
+        match self {
+            Values::List(values) => values.clone(),
+            Values::Linear(from, to, steps) => spaced(*steps, &|t| from + (to - from) * t),
+            Values::Log(from, to, steps) => spaced(*steps, &|t| from * (to / from).powf(t)),
+        }
+    }
+}
+
+// rounds to 12 significant digits so 0.001 doesn't come out as 0.0010000000000000002
+fn tidy(v: f64) -> f64 {
+    if v == 0. || !v.is_finite() {
+        return v;
+    }
+    let scale = 10f64.powi(11 - v.abs().log10().floor() as i32);
+    (v * scale).round() / scale
+}
+
+// A grid of parameter values to run every combination of, read from a RON file:
+// (
+//     ticks: 100000,
+//     replicates: 4,
+//     target_generation: Some(20),
+//     params: [
+//         ("genes.mut_rate", Log(0.0001, 0.01, 5)),
+//         ("physics.brown_scale", List([4.0, 8.0])),
+//     ],
+// )
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sweep {
+    pub ticks: u64,
+    // seeds run at every point, the same seeds at each point
+    #[serde(default = "one")]
+    pub replicates: u64,
+    // time how long the highest generation takes to reach this
+    #[serde(default)]
+    pub target_generation: Option<u16>,
+    pub params: Vec<(String, Values)>,
+}
+
+fn one() -> u64 {
+    1
+}
+
+// One combination of parameter values
+pub struct Point {
+    pub values: Vec<f64>,
+    pub config: Config,
+}
+
+impl Sweep {
+    pub fn load(path: &Path) -> Result<Sweep, ConfigError> {
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let sweep: Sweep = ron::from_str(&text).map_err(ConfigError::Parse)?;
+        sweep.validate()?;
+        Ok(sweep)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.replicates == 0 {
+            return Err(ConfigError::Invalid {
+                field: "replicates",
+                reason: "must be at least 1".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    // Every combination of values applied to `base`, last parameter varying fastest
+    pub fn points(&self, base: &Config) -> Result<Vec<Point>, ConfigError> {
+        self.validate()?;
+        let mut points = vec![Point {
+            values: vec![],
+            config: base.clone(),
+        }];
+        for (key, values) in &self.params {
+            let values = values.expand();
+            if values.is_empty() {
+                return Err(ConfigError::Invalid {
+                    field: "params",
+                    reason: format!("{} has no values", key),
+                });
+            }
+            let mut next = Vec::with_capacity(points.len() * values.len());
+            for point in &points {
+                for v in &values {
+                    let mut config = point.config.clone();
+                    config.set(key, *v)?;
+                    let mut values = point.values.clone();
+                    values.push(*v);
+                    next.push(Point { values, config });
+                }
+            }
+            points = next;
+        }
+        for point in &points {
+            point.config.validate()?;
+        }
+        Ok(points)
+    }
+
+    // replicates runs for every point, run names are point<i>-<seed>
+    pub fn specs(&self, points: &[Point], base_seed: u64) -> Vec<RunSpec> {
+        points
+            .iter()
+            .enumerate()
+            .flat_map(|(i, point)| {
+                (0..self.replicates).map(move |r| {
+                    let seed = base_seed.wrapping_add(r);
+                    RunSpec {
+                        name: format!("point{}-{}", i, seed),
+                        config: point.config.clone(),
+                        seed,
+                        ticks: self.ticks,
+                        target_generation: self.target_generation,
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+// Outcomes of all replicates at one point
+#[derive(Serialize)]
+pub struct PointSummary {
+    pub point: usize,
+    pub values: Vec<f64>,
+    pub runs: usize,
+    pub final_blobs: Spread,
+    pub max_generation: Spread,
+    // runs that reached the target generation
+    pub reached: usize,
+    // over the runs that reached it
+    pub reached_tick: Spread,
+}
+
+#[derive(Serialize)]
+pub struct SweepSummary {
+    pub keys: Vec<String>,
+    pub target_generation: Option<u16>,
+    pub points: Vec<PointSummary>,
+}
+impl SweepSummary {
+    // `runs` as returned by run_batch for `sweep.specs(points, ..)`
+    pub fn new(sweep: &Sweep, points: &[Point], runs: &[RunSummary]) -> Self {
+        let per_point = sweep.replicates as usize;
+        SweepSummary {
+            keys: sweep.params.iter().map(|(key, _)| key.clone()).collect(),
+            target_generation: sweep.target_generation,
+            points: points
+                .iter()
+                .zip(runs.chunks(per_point.max(1)))
+                .enumerate()
+                .map(|(i, (point, runs))| PointSummary {
+                    point: i,
+                    values: point.values.clone(),
+                    runs: runs.len(),
+                    final_blobs: Spread::of(runs.iter().map(|r| r.final_blobs as f32)),
+                    max_generation: Spread::of(runs.iter().map(|r| r.max_generation as f32)),
+                    reached: runs.iter().filter(|r| r.reached_target.is_some()).count(),
+                    reached_tick: Spread::of(
+                        runs.iter()
+                            .filter_map(|r| r.reached_target)
+                            .map(|t| t as f32),
+                    ),
+                })
+                .collect(),
+        }
+    }
+
+    fn header(&self) -> Vec<String> {
+        let mut header = vec!["point".to_string()];
+        header.extend(self.keys.iter().cloned());
+        header.extend(
+            [
+                "runs",
+                "final_blobs_mean",
+                "final_blobs_min",
+                "final_blobs_max",
+                "max_generation_mean",
+                "max_generation_min",
+                "max_generation_max",
+                "reached",
+                "reached_tick_mean",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        );
+        header
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.points
+            .iter()
+            .map(|p| {
+                let mut row = vec![p.point.to_string()];
+                row.extend(p.values.iter().map(|v| v.to_string()));
+                row.extend([
+                    p.runs.to_string(),
+                    format!("{:.1}", p.final_blobs.mean),
+                    p.final_blobs.min.to_string(),
+                    p.final_blobs.max.to_string(),
+                    format!("{:.1}", p.max_generation.mean),
+                    p.max_generation.min.to_string(),
+                    p.max_generation.max.to_string(),
+                    p.reached.to_string(),
+                    if p.reached > 0 {
+                        format!("{:.0}", p.reached_tick.mean)
+                    } else {
+                        String::new()
+                    },
+                ]);
+                row
+            })
+            .collect()
+    }
+
+    // sweep.csv and sweep.json
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        let json = BufWriter::new(File::create(dir.join("sweep.json"))?);
+        serde_json::to_writer_pretty(json, self)?;
+
+        let mut csv = BufWriter::new(File::create(dir.join("sweep.csv"))?);
+        writeln!(csv, "{}", self.header().join(","))?;
+        for row in self.rows() {
+            writeln!(csv, "{}", row.join(","))?;
+        }
+        csv.flush()
+    }
+
+    // the csv, but with aligned columns
+    pub fn table(&self) -> String {
+        let header = self.header();
+        let rows = self.rows();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|c| {
+                rows.iter()
+                    .map(|row| row[c].len())
+                    .chain([header[c].len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let line = |cells: &[String]| {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, w)| format!("{:>w$}", cell, w = w))
+                .collect::<Vec<_>>()
+                .join("  ")
+        };
+        let mut table = line(&header);
+        for row in &rows {
+            table.push('\n');
+            table.push_str(&line(row));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sweep::{Sweep, Values},
+        Config,
+    };
+
+    #[test]
+    fn grid_is_every_combination() {
+        let sweep: Sweep = ron::from_str(
+            r#"(
+                ticks: 10,
+                replicates: 2,
+                params: [
+                    ("genes.mut_rate", Log(0.0001, 0.01, 3)),
+                    ("food.min_food", List([16, 64])),
+                ],
+            )"#,
+        )
+        .unwrap();
+        let points = sweep.points(&Config::default()).unwrap();
+        assert_eq!(points.len(), 6);
+        assert_eq!(points[2].config.genes.mut_rate, 0.001);
+        assert_eq!(points[2].config.food.min_food, 16);
+        assert_eq!(points[3].config.food.min_food, 64);
+        assert_eq!(sweep.specs(&points, 5).len(), 12);
+
+        assert_eq!(Values::Linear(1., 2., 3).expand(), vec![1., 1.5, 2.]);
+
+        let none = Sweep {
+            replicates: 0,
+            ..sweep
+        };
+        assert!(none.points(&Config::default()).is_err());
+    }
+}
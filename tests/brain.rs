@@ -0,0 +1,90 @@
+use bevy::{
+    prelude::{App, Transform},
+    MinimalPlugins,
+};
+use blobs::{
+    blob::Blob,
+    brain::{Actions, Senses},
+    Brain, BrainBuilder, Config, SimulationPlugin,
+};
+
+// Pushes right as hard as it can and burns energy doing it
+struct Push;
+impl Brain for Push {
+    fn think(&mut self, _: &Senses) -> Actions {
+        [1., 0., 0., 0.]
+    }
+
+    fn energy_cost(&self) -> f32 {
+        0.5
+    }
+}
+
+// Leaves everything to the drift
+struct Still;
+impl Brain for Still {
+    fn think(&mut self, _: &Senses) -> Actions {
+        [0.; 4]
+    }
+}
+
+// (id, x, y, energy, age) of every blob, by id
+fn run(brains: BrainBuilder, config: &Config) -> Vec<(u64, f32, f32, f32, f32)> {
+    let mut app = App::new();
+    app.insert_resource(brains)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(SimulationPlugin::new(config.clone(), 3));
+    // the first blobs arrive after a second
+    for _ in 0..90 {
+        app.update();
+    }
+
+    let mut blobs: Vec<_> = app
+        .world
+        .query::<(&Blob, &Transform)>()
+        .iter(&app.world)
+        .map(|(blob, trans)| {
+            (
+                blob.id,
+                trans.translation.x,
+                trans.translation.y,
+                blob.energy,
+                blob.age,
+            )
+        })
+        .collect();
+    blobs.sort_by_key(|b| b.0);
+    blobs
+}
+
+#[test]
+fn custom_brain_steers_blobs() {
+    let mut config = Config::default();
+    // food would make energy depend on where a blob went
+    config.food.nutrition = 0.;
+    let still = run(BrainBuilder(Box::new(|_| Box::new(Still))), &config);
+    let push = run(BrainBuilder(Box::new(|_| Box::new(Push))), &config);
+    assert!(!still.is_empty());
+    assert_eq!(still.len(), push.len());
+
+    let w = config.world.width;
+    let per_tick = config.blobs.move_cost + 0.5;
+    for (a, b) in still.iter().zip(&push) {
+        assert_eq!(a.0, b.0);
+        // same drift, so pushing only ever moved them right
+        let dx = ((b.1 - a.1) % w + w) % w;
+        assert!(dx > 10. && dx < w / 2., "moved {}", dx);
+        assert_eq!(a.2, b.2);
+        // every think, counted by age, cost the move and the brain's own cost
+        assert_eq!(a.4, b.4);
+        let thoughts = (a.4 / 0.001).round();
+        assert!(thoughts > 0.);
+        let spent = a.3 - b.3;
+        assert!(
+            (spent - thoughts * per_tick).abs() < 1e-3,
+            "spent {} in {} ticks",
+            spent,
+            thoughts
+        );
+    }
+}
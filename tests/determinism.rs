@@ -0,0 +1,85 @@
+use std::{
+    fs,
+    process::{Command, Stdio},
+};
+
+use bevy::{
+    prelude::{App, Transform},
+    MinimalPlugins,
+};
+use blobs::{blob::Blob, Config, Genes, SimulationPlugin};
+
+// (gene, energy, x, y) of every blob after running headless for a while
+fn run(seed: u64, ticks: u32) -> Vec<(Genes, u32, u32, u32)> {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(SimulationPlugin::new(Config::default(), seed));
+    for _ in 0..ticks {
+        app.update();
+    }
+
+    let mut state: Vec<_> = app
+        .world
+        .query::<(&Blob, &Genes, &Transform)>()
+        .iter(&app.world)
+        .map(|(blob, genes, trans)| {
+            (
+                genes.clone(),
+                blob.energy.to_bits(),
+                trans.translation.x.to_bits(),
+                trans.translation.y.to_bits(),
+            )
+        })
+        .collect();
+    state.sort();
+    state
+}
+
+#[test]
+fn same_seed_same_world() {
+    let a = run(1234, 300);
+    assert!(!a.is_empty());
+    assert_eq!(a, run(1234, 300));
+    assert_ne!(a, run(4321, 300));
+}
+
+// Hash seeds and anything else picked per process can't leak into a run, so the same
+// seed has to give the same logs from separate runs of the binary. The world is small
+// and crowded so blobs often eat several pieces of food in one tick
+#[test]
+fn same_seed_same_logs_across_processes() {
+    let out = std::env::temp_dir().join(format!("blobs-determinism-{}", std::process::id()));
+    fs::create_dir_all(&out).unwrap();
+    let config = out.join("crowded.ron");
+    fs::write(
+        &config,
+        "(world: (width: 320.0, height: 180.0), \
+        food: (min_food: 128, spawn_batch: 32), \
+        blobs: (eat_radius_sq: 40.0))",
+    )
+    .unwrap();
+
+    let logs: Vec<_> = (0..3)
+        .map(|i| {
+            let dir = out.join(i.to_string());
+            let status = Command::new(env!("CARGO_BIN_EXE_blobs"))
+                .args(["--headless", "--seed", "7", "--ticks", "300"])
+                .args(["--metrics", "csv", "--lineage", "--config"])
+                .arg(&config)
+                .arg("--out")
+                .arg(&dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+            let read = |name| fs::read_to_string(dir.join(name)).unwrap();
+            (read("metrics.csv"), read("lineage.csv"))
+        })
+        .collect();
+    let _ = fs::remove_dir_all(&out);
+
+    assert!(logs[0].1.lines().count() > 1);
+    assert_eq!(logs[0], logs[1]);
+    assert_eq!(logs[0], logs[2]);
+}